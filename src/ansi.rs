@@ -0,0 +1,104 @@
+//! Handling of the ANSI escape sequences in coloured lines.
+//!
+use std::ops::Range;
+
+/// Remove the escape sequences, leaving the original text
+pub fn strip(s: &str) -> String {
+    let mut stripped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            skip_sequence(&mut chars, &mut String::new());
+        } else {
+            stripped.push(c);
+        }
+    }
+    stripped
+}
+
+/// Wrap each range in the `start` sequence. The ranges are offsets into the text with
+/// the escape sequences stripped, and must be sorted and non-overlapping.
+///
+/// `start` is written again after any sequence within a range, so the highlight
+/// survives the colour changes of the line. At the end of a range the colours in
+/// effect before the highlight are restored.
+pub fn highlight(s: &str, ranges: &[Range<usize>], start: &str) -> String {
+    let mut highlighted = String::with_capacity(s.len() + ranges.len() * 8);
+    let mut ranges = ranges.iter().filter(|range| !range.is_empty()).peekable();
+    let mut offset = 0;
+    let mut in_range = false;
+    // The sequences applied since the last reset
+    let mut active = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            let mut sequence = String::from(c);
+            skip_sequence(&mut chars, &mut sequence);
+            if is_reset(&sequence) {
+                active.clear();
+            }
+            active.push_str(&sequence);
+            highlighted.push_str(&sequence);
+            if in_range {
+                highlighted.push_str(start);
+            }
+            continue;
+        }
+        if ranges.peek().is_some_and(|range| range.start == offset) {
+            highlighted.push_str(start);
+            in_range = true;
+        }
+        highlighted.push(c);
+        offset += c.len_utf8();
+        if in_range && ranges.peek().is_some_and(|range| range.end <= offset) {
+            highlighted.push_str("\x1b[0m");
+            highlighted.push_str(&active);
+            in_range = false;
+            ranges.next();
+        }
+    }
+    if in_range {
+        highlighted.push_str("\x1b[0m");
+    }
+    highlighted
+}
+
+/// Whether a sequence clears all attributes, ie. `\x1b[m`, `\x1b[0m` or `\x1b[0;..m`
+fn is_reset(sequence: &str) -> bool {
+    sequence == "\x1b[m" || sequence == "\x1b[0m" || sequence.starts_with("\x1b[0;")
+}
+
+/// Consume the rest of an escape sequence after the `\x1b`, copying it to `out`
+fn skip_sequence(chars: &mut std::str::Chars, out: &mut String) {
+    let Some(c) = chars.next() else {
+        return;
+    };
+    out.push(c);
+    if c != '[' {
+        return;
+    }
+    // Control sequences end with a byte in the range @ to ~
+    for c in chars.by_ref() {
+        out.push(c);
+        if ('@'..='~').contains(&c) {
+            return;
+        }
+    }
+}
+
+/// Cut the text down to `width` characters, keeping the escape sequences
+pub fn truncate(s: &str, width: usize) -> String {
+    let mut truncated = String::with_capacity(s.len());
+    let mut count = 0;
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            truncated.push(c);
+            skip_sequence(&mut chars, &mut truncated);
+        } else if count < width {
+            truncated.push(c);
+            count += 1;
+        }
+    }
+    truncated
+}
@@ -0,0 +1,122 @@
+//! Defaults read from the config files.
+//!
+//! The user config is read from `$XDG_CONFIG_HOME/tracing_log_viewer/config.toml`,
+//! falling back to `~/.config`, and is overridden by a `.logviewer.toml` in the current
+//! directory or its nearest ancestor which has one. Anything given on the command line
+//! overrides both, eg.
+//!
+//! ```toml
+//! pager_args = ["-S", "+G"]
+//! theme = "light"
+//! target_colors = "crate"
+//! timestamps = "delta"
+//! tz = "local"
+//!
+//! [styles]
+//! error = "bold #d70000"
+//! target = "underline 242"
+//!
+//! [presets.db-debug]
+//! filter = "my_crate::db=trace,warn"
+//! grep = "query"
+//! ```
+//!
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::ColorChoice;
+use crate::parse::LogType;
+use crate::theme::{TargetColours, ThemeName};
+use crate::time::TimestampMode;
+
+const USER_CONFIG: &str = "tracing_log_viewer/config.toml";
+const PROJECT_CONFIG: &str = ".logviewer.toml";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Used when no pager arguments are given on the command line
+    pub pager_args: Option<Vec<String>>,
+    pub color: Option<ColorChoice>,
+    pub theme: Option<ThemeName>,
+    #[serde(alias = "target_colours")]
+    pub target_colors: Option<TargetColours>,
+    pub timestamps: Option<TimestampMode>,
+    /// The time zone, as for `--tz`
+    pub tz: Option<String>,
+    pub time_format: Option<String>,
+    /// Styles replacing those of the theme, by component name
+    pub styles: BTreeMap<String, String>,
+    pub presets: BTreeMap<String, Preset>,
+}
+
+/// A named set of filters, selected with `--preset`
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Preset {
+    pub level: Option<LogType>,
+    pub only: Vec<LogType>,
+    /// EnvFilter directives, as for `--filter`
+    pub filter: Option<String>,
+    pub grep: Option<String>,
+}
+
+impl Config {
+    /// Read and combine the config files which exist
+    pub fn load() -> Result<Self, String> {
+        let mut config = Self::default();
+        let user_config = env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+            .map(|dir| dir.join(USER_CONFIG));
+        let project_config = env::current_dir().ok().and_then(|dir| {
+            dir.ancestors()
+                .map(|dir| dir.join(PROJECT_CONFIG))
+                .find(|path| path.is_file())
+        });
+        for path in user_config.into_iter().chain(project_config) {
+            if path.is_file() {
+                config.merge(Self::read(&path)?);
+            }
+        }
+        Ok(config)
+    }
+
+    fn read(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+        toml::from_str(&contents).map_err(|e| format!("Invalid config {}: {e}", path.display()))
+    }
+
+    /// Apply the settings of `other` over our own
+    fn merge(&mut self, other: Self) {
+        self.pager_args = other.pager_args.or(self.pager_args.take());
+        self.color = other.color.or(self.color);
+        self.theme = other.theme.or(self.theme);
+        self.target_colors = other.target_colors.or(self.target_colors);
+        self.timestamps = other.timestamps.or(self.timestamps);
+        self.tz = other.tz.or(self.tz.take());
+        self.time_format = other.time_format.or(self.time_format.take());
+        self.styles.extend(other.styles);
+        self.presets.extend(other.presets);
+    }
+
+    pub fn preset(&self, name: &str) -> Result<&Preset, String> {
+        self.presets.get(name).ok_or_else(|| {
+            let names = self.presets.keys().cloned().collect::<Vec<_>>();
+            if names.is_empty() {
+                format!("Unknown preset {name:?}, no presets are configured")
+            } else {
+                format!(
+                    "Unknown preset {name:?}, expected one of {}",
+                    names.join(", ")
+                )
+            }
+        })
+    }
+}
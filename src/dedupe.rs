@@ -0,0 +1,102 @@
+//! Folding of repeated consecutive records, for `--dedupe`.
+//!
+//! Records are the same if they have the same level, target and message, including
+//! any fields and continuation lines. The span context and timestamp are ignored, and
+//! with `--dedupe=numbers` so are any numbers in the message.
+//!
+use std::io;
+
+use clap::ValueEnum;
+use regex::Regex;
+
+use crate::record::{Record, Repeats};
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum DedupeMode {
+    /// Fold records with identical messages
+    Exact,
+    /// Fold records whose messages differ only in their numbers
+    Numbers,
+}
+
+/// Iterator over records, folding each run of the same record into its first record
+pub struct Dedupe<I> {
+    records: I,
+    /// Matches the numbers to ignore, if they are ignored
+    numbers: Option<Regex>,
+    /// The run being folded, along with the key it was matched by
+    pending: Option<(usize, Record, Option<String>)>,
+}
+
+impl<I> Dedupe<I> {
+    pub fn new(records: I, mode: DedupeMode) -> Self {
+        let numbers = match mode {
+            DedupeMode::Exact => None,
+            DedupeMode::Numbers => Some(Regex::new(r"\d+(\.\d+)?").expect("the pattern is valid")),
+        };
+        Self {
+            records,
+            numbers,
+            pending: None,
+        }
+    }
+
+    /// The parts of a record which must match, or None if it can't be folded
+    fn key(&self, record: &Record) -> Option<String> {
+        let format = record.format?;
+        if record.line_shown {
+            return None;
+        }
+        let mut key = format!("{:?} {}:", format.log_type, format.target(&record.line));
+        key.push_str(&record.line[format.path_end..]);
+        for line in &record.continuation {
+            key.push('\n');
+            key.push_str(line);
+        }
+        Some(match &self.numbers {
+            Some(numbers) => numbers.replace_all(&key, "#").into_owned(),
+            None => key,
+        })
+    }
+}
+
+impl<I: Iterator<Item = io::Result<(usize, Record)>>> Iterator for Dedupe<I> {
+    type Item = io::Result<(usize, Record)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (source, record) = match self.records.next() {
+                Some(Ok(next)) => next,
+                // The input is waiting, so the run so far is all there is for now
+                Some(Err(e)) if e.kind() == io::ErrorKind::WouldBlock => {
+                    return match self.pending.take() {
+                        Some((source, record, _)) => Some(Ok((source, record))),
+                        None => Some(Err(e)),
+                    };
+                }
+                Some(Err(e)) => return Some(Err(e)),
+                None => {
+                    return self
+                        .pending
+                        .take()
+                        .map(|(source, record, _)| Ok((source, record)));
+                }
+            };
+            let key = self.key(&record);
+            if let Some((_, first, Some(pending_key))) = &mut self.pending
+                && key.as_ref() == Some(pending_key)
+            {
+                let repeats = first.repeats.get_or_insert(Repeats {
+                    count: 1,
+                    last: None,
+                });
+                repeats.count += 1;
+                repeats.last = record.timestamp();
+                continue;
+            }
+            if let Some((source, record, _)) = self.pending.replace((source, record, key)) {
+                return Some(Ok((source, record)));
+            }
+        }
+    }
+}
@@ -0,0 +1,557 @@
+//! Selection of which log records are shown.
+//!
+use std::cell::OnceCell;
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+use crate::parse::{self, LineFormat, LogType};
+use crate::time::{self, TimeArg};
+
+/// The criteria a record must meet to be shown. An empty filter shows everything.
+#[derive(Default)]
+pub struct Filter {
+    /// The least severe level to show
+    pub min_level: Option<LogType>,
+    /// If not empty, only these levels are shown
+    pub only: Vec<LogType>,
+    pub targets: Option<TargetFilter>,
+    pub time_range: TimeRange,
+}
+
+impl Filter {
+    /// Whether the line, which has been parsed to `line_format`, should be shown
+    pub fn matches(&self, line: &str, line_format: &LineFormat) -> bool {
+        let log_type = line_format.log_type;
+        if let Some(min_level) = self.min_level
+            && log_type > min_level
+        {
+            return false;
+        }
+        if !self.only.is_empty() && !self.only.contains(&log_type) {
+            return false;
+        }
+        if !self.time_range.is_unbounded()
+            && let Some(timestamp) =
+                time::parse_timestamp(&line[line_format.tz_start..line_format.tz_end])
+            && !self.time_range.contains(timestamp)
+        {
+            return false;
+        }
+        if let Some(targets) = &self.targets {
+            let spans = parse::parse_spans(line, line_format)
+                .iter()
+                .map(|span| {
+                    (
+                        &line[span.name_start..span.name_end],
+                        &line[span.fields_start..span.fields_end],
+                    )
+                })
+                .collect::<Vec<_>>();
+            if !targets.enabled(log_type, line_format.target(line), &spans) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// A single `target[span{field=value}]=level` directive, as used by `EnvFilter`
+#[derive(Clone, Debug)]
+struct Directive {
+    target: Option<String>,
+    span: Option<String>,
+    /// The names of the fields a span must have, along with their values if given
+    fields: Vec<(String, Option<String>)>,
+    /// None if the directive turns logging off
+    level: Option<LogType>,
+}
+
+/// Target filtering using `RUST_LOG` syntax, eg. `my_crate=debug,hyper=warn,info`.
+///
+/// As with `EnvFilter`, the directive with the longest matching target prefix decides
+/// the level, a bare level sets the default, and a record matching no directive is hidden.
+/// Directives naming a span or its fields apply to records inside a matching span, and
+/// show the records they allow in addition to those the other directives allow.
+#[derive(Clone, Debug)]
+pub struct TargetFilter {
+    statics: Vec<Directive>,
+    dynamics: Vec<Directive>,
+}
+
+impl std::str::FromStr for TargetFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut statics = Vec::new();
+        let mut dynamics = Vec::new();
+        for directive in split_directives(s) {
+            let directive = parse_directive(directive)?;
+            if directive.span.is_some() || !directive.fields.is_empty() {
+                dynamics.push(directive);
+            } else {
+                statics.push(directive);
+            }
+        }
+        // Most specific first, so that the first match decides
+        statics.sort_by_key(|d| std::cmp::Reverse(d.target.as_ref().map_or(0, |t| t.len() + 1)));
+        Ok(Self { statics, dynamics })
+    }
+}
+
+impl TargetFilter {
+    pub fn enabled(&self, log_type: LogType, target: &str, spans: &[(&str, &str)]) -> bool {
+        let allows = |d: &Directive| d.level.is_some_and(|level| log_type <= level);
+        let target_matches = |d: &Directive| {
+            d.target
+                .as_ref()
+                .is_none_or(|t| target.starts_with(t.as_str()))
+        };
+
+        let in_span = |d: &Directive| {
+            spans.iter().any(|(name, fields)| {
+                d.span.as_ref().is_none_or(|span| span == name)
+                    && d.fields.iter().all(|field| has_field(fields, field))
+            })
+        };
+        self.dynamics
+            .iter()
+            .filter(|d| target_matches(d) && in_span(d))
+            .any(allows)
+            || self
+                .statics
+                .iter()
+                .find(|d| target_matches(d))
+                .is_some_and(allows)
+    }
+}
+
+/// Whether the fields of a span, eg. `id=42 method="GET"`, include the field, which
+/// matches any value if it has none
+fn has_field(fields: &str, (name, value): &(String, Option<String>)) -> bool {
+    parse::parse_fields(fields, 0, fields.len())
+        .iter()
+        .any(|field| {
+            let field_value = &fields[field.value_start..field.value_end];
+            &fields[field.key_start..field.key_end] == name
+                && value.as_ref().is_none_or(|value| {
+                    field_value == value || field_value.trim_matches('"') == value
+                })
+        })
+}
+
+/// Split on the commas which are not within a span's brackets
+fn split_directives(s: &str) -> Vec<&str> {
+    let mut directives = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '[' | '{' => depth += 1,
+            ']' | '}' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                directives.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    directives.push(&s[start..]);
+    directives
+        .into_iter()
+        .map(str::trim)
+        .filter(|d| !d.is_empty())
+        .collect()
+}
+
+fn parse_directive(s: &str) -> Result<Directive, String> {
+    if let Some(level) = parse_level_filter(s) {
+        return Ok(Directive {
+            target: None,
+            span: None,
+            fields: Vec::new(),
+            level,
+        });
+    }
+
+    // Span fields may contain `=`, so look for the level after the span
+    let (selector, level) = if s.contains('[') {
+        let close = s
+            .rfind(']')
+            .ok_or_else(|| format!("unclosed '[' in {s:?}"))?;
+        (&s[..=close], &s[close + 1..])
+    } else {
+        match s.find('=') {
+            Some(eq) => (&s[..eq], &s[eq..]),
+            None => (s, ""),
+        }
+    };
+    let level = match level.strip_prefix('=') {
+        Some(level) => {
+            parse_level_filter(level).ok_or_else(|| format!("invalid level {level:?} in {s:?}"))?
+        }
+        None if level.is_empty() => Some(LogType::Trace),
+        None => return Err(format!("invalid directive {s:?}")),
+    };
+
+    let (target, span_selector) = match selector.split_once('[') {
+        Some((target, span)) => (target, Some(span.trim_end_matches(']'))),
+        None => (selector, None),
+    };
+    let (span, fields) = match span_selector {
+        Some(span) => match span.split_once('{') {
+            Some((name, fields)) => (
+                name,
+                fields
+                    .trim_end_matches('}')
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|f| !f.is_empty())
+                    .map(|f| match f.split_once('=') {
+                        Some((name, value)) => (name.to_string(), Some(value.to_string())),
+                        None => (f.to_string(), None),
+                    })
+                    .collect(),
+            ),
+            None => (span, Vec::new()),
+        },
+        None => ("", Vec::new()),
+    };
+
+    Ok(Directive {
+        target: (!target.is_empty()).then(|| target.to_string()),
+        span: (!span.is_empty()).then(|| span.to_string()),
+        fields,
+        level,
+    })
+}
+
+/// Parse a level as accepted by `LevelFilter`, returning Some(None) for `off`
+fn parse_level_filter(s: &str) -> Option<Option<LogType>> {
+    match s.trim().to_ascii_lowercase().as_str() {
+        "off" | "0" => Some(None),
+        "1" => Some(Some(LogType::Error)),
+        "2" => Some(Some(LogType::Warn)),
+        "3" => Some(Some(LogType::Info)),
+        "4" => Some(Some(LogType::Debug)),
+        "5" => Some(Some(LogType::Trace)),
+        level => LogType::parse(level).map(Some),
+    }
+}
+
+/// The earliest and latest times to show, either of which may be unbounded
+pub type Bounds = (Option<DateTime<Utc>>, Option<DateTime<Utc>>);
+
+/// The bounds of the records to show.
+///
+/// A time of day is taken to be on the date of the first record, before any filtering
+/// and of whichever log starts first, or for `until`, on
+/// the date of `since`, moving to the next day if that would end the range before it starts.
+#[derive(Default)]
+pub struct TimeRange {
+    since: Option<TimeArg>,
+    until: Option<TimeArg>,
+    resolved: OnceCell<Bounds>,
+}
+
+impl TimeRange {
+    pub fn new(since: Option<TimeArg>, until: Option<TimeArg>) -> Self {
+        Self {
+            since,
+            until,
+            resolved: OnceCell::new(),
+        }
+    }
+
+    pub fn is_unbounded(&self) -> bool {
+        self.since.is_none() && self.until.is_none()
+    }
+
+    /// Whether the bounds have been resolved against the first record
+    pub fn is_resolved(&self) -> bool {
+        self.resolved.get().is_some()
+    }
+
+    /// The bounds, if they do not depend on the date of the logs
+    pub fn absolute(&self) -> Bounds {
+        (
+            self.since.and_then(|since| since.absolute()),
+            self.until.and_then(|until| until.absolute()),
+        )
+    }
+
+    /// The bounds, resolved against the timestamp of the first record
+    pub fn bounds(&self, first: DateTime<Utc>) -> Bounds {
+        *self.resolved.get_or_init(|| {
+            let since = self.since.map(|since| since.on(first.date_naive()));
+            let until = self.until.map(|until| {
+                let date = since.unwrap_or(first).date_naive();
+                let until_time = until.on(date);
+                match (until, since) {
+                    (TimeArg::TimeOfDay(_), Some(since)) if until_time < since => {
+                        until_time + TimeDelta::days(1)
+                    }
+                    _ => until_time,
+                }
+            });
+            (since, until)
+        })
+    }
+
+    pub fn contains(&self, timestamp: DateTime<Utc>) -> bool {
+        let (since, until) = self.bounds(timestamp);
+        since.is_none_or(|since| timestamp >= since) && until.is_none_or(|until| timestamp <= until)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time;
+
+    fn enabled(filter: &str, log_type: LogType, target: &str, spans: &[(&str, &str)]) -> bool {
+        let filter: TargetFilter = filter.parse().expect("the filter is valid");
+        filter.enabled(log_type, target, spans)
+    }
+
+    #[test]
+    fn longest_target_prefix_wins() {
+        let filter = "my_crate=warn,my_crate::db=trace,my_crate::db::pool=error";
+        assert!(enabled(filter, LogType::Trace, "my_crate::db", &[]));
+        assert!(!enabled(filter, LogType::Info, "my_crate::http", &[]));
+        assert!(enabled(filter, LogType::Warn, "my_crate::http", &[]));
+        assert!(!enabled(filter, LogType::Warn, "my_crate::db::pool", &[]));
+        // A record matching no directive is hidden
+        assert!(!enabled(filter, LogType::Error, "hyper", &[]));
+    }
+
+    #[test]
+    fn bare_level_is_the_default() {
+        assert!(enabled("info", LogType::Info, "hyper", &[]));
+        assert!(!enabled("info", LogType::Debug, "hyper", &[]));
+        assert!(enabled(
+            "info,my_crate=trace",
+            LogType::Trace,
+            "my_crate",
+            &[]
+        ));
+        // A target without a level enables everything
+        assert!(enabled(
+            "warn,my_crate",
+            LogType::Trace,
+            "my_crate::db",
+            &[]
+        ));
+    }
+
+    #[test]
+    fn off() {
+        assert!(!enabled(
+            "trace,hyper=off",
+            LogType::Error,
+            "hyper::client",
+            &[]
+        ));
+        assert!(enabled("trace,hyper=off", LogType::Trace, "my_crate", &[]));
+        assert!(!enabled("off", LogType::Error, "my_crate", &[]));
+    }
+
+    #[test]
+    fn numeric_levels() {
+        assert!(enabled("my_crate=1", LogType::Error, "my_crate", &[]));
+        assert!(!enabled("my_crate=1", LogType::Warn, "my_crate", &[]));
+        assert!(enabled("my_crate=3", LogType::Info, "my_crate", &[]));
+        assert!(enabled("5", LogType::Trace, "my_crate", &[]));
+        assert!(!enabled("my_crate=0", LogType::Error, "my_crate", &[]));
+    }
+
+    #[test]
+    fn invalid_directives() {
+        for filter in [
+            "my_crate=loud",
+            "my_crate[request=info",
+            "my_crate=info=debug",
+        ] {
+            assert!(
+                filter.parse::<TargetFilter>().is_err(),
+                "{filter:?} should not parse"
+            );
+        }
+    }
+
+    #[test]
+    fn span_fields() {
+        let spans = [("request", r#"id=42 method="GET""#)];
+        assert!(enabled(
+            "[request{id}]=debug",
+            LogType::Debug,
+            "my_crate",
+            &spans
+        ));
+        assert!(enabled(
+            "[request{id=42}]=debug",
+            LogType::Debug,
+            "my_crate",
+            &spans
+        ));
+        assert!(enabled(
+            "[{method=GET}]=debug",
+            LogType::Debug,
+            "my_crate",
+            &spans
+        ));
+        assert!(enabled(
+            "[request{id=42,method}]=debug",
+            LogType::Debug,
+            "my_crate",
+            &spans
+        ));
+        assert!(!enabled(
+            "[request{id=7}]=debug",
+            LogType::Debug,
+            "my_crate",
+            &spans
+        ));
+        assert!(!enabled(
+            "[request{user}]=debug",
+            LogType::Debug,
+            "my_crate",
+            &spans
+        ));
+        assert!(!enabled(
+            "[response]=debug",
+            LogType::Debug,
+            "my_crate",
+            &spans
+        ));
+        assert!(!enabled(
+            "other[request]=debug",
+            LogType::Debug,
+            "my_crate",
+            &spans
+        ));
+    }
+
+    #[test]
+    fn span_directives_add_to_the_others() {
+        let inside = [("request", "id=42")];
+        // A span directive enables more within the span...
+        assert!(enabled(
+            "warn,[request]=debug",
+            LogType::Debug,
+            "my_crate::db",
+            &inside
+        ));
+        assert!(!enabled(
+            "warn,[request]=debug",
+            LogType::Debug,
+            "my_crate::db",
+            &[]
+        ));
+        // ...but never hides what the other directives show
+        assert!(enabled(
+            "debug,my_crate[request]=warn",
+            LogType::Info,
+            "my_crate::db",
+            &inside
+        ));
+        assert!(enabled(
+            "info,[request]=off",
+            LogType::Info,
+            "my_crate::db",
+            &inside
+        ));
+        assert!(!enabled(
+            "info,[request]=off",
+            LogType::Debug,
+            "my_crate::db",
+            &inside
+        ));
+    }
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        time::parse_timestamp(s).expect("the timestamp is valid")
+    }
+
+    fn range(since: Option<&str>, until: Option<&str>) -> TimeRange {
+        let arg = |s: &str| time::parse_time_arg(s).expect("the time is valid");
+        TimeRange::new(since.map(arg), until.map(arg))
+    }
+
+    #[test]
+    fn times_of_day_are_on_the_first_date() {
+        let range = range(Some("05:00"), Some("06:30"));
+        assert_eq!(range.absolute(), (None, None));
+        assert_eq!(
+            range.bounds(utc("2025-08-28T04:00:00Z")),
+            (
+                Some(utc("2025-08-28T05:00:00Z")),
+                Some(utc("2025-08-28T06:30:00Z"))
+            )
+        );
+        assert!(range.contains(utc("2025-08-28T05:00:00Z")));
+        assert!(range.contains(utc("2025-08-28T06:30:00Z")));
+        assert!(!range.contains(utc("2025-08-28T04:59:59Z")));
+        assert!(!range.contains(utc("2025-08-29T05:30:00Z")));
+    }
+
+    #[test]
+    fn until_before_since_rolls_over() {
+        let range = range(Some("23:00"), Some("01:00"));
+        assert_eq!(
+            range.bounds(utc("2025-08-28T22:00:00Z")),
+            (
+                Some(utc("2025-08-28T23:00:00Z")),
+                Some(utc("2025-08-29T01:00:00Z"))
+            )
+        );
+        assert!(range.contains(utc("2025-08-29T00:30:00Z")));
+    }
+
+    #[test]
+    fn until_is_on_the_date_of_since() {
+        let range = range(Some("2025-08-30T12:00:00Z"), Some("13:00"));
+        assert_eq!(
+            range.bounds(utc("2025-08-28T04:00:00Z")),
+            (
+                Some(utc("2025-08-30T12:00:00Z")),
+                Some(utc("2025-08-30T13:00:00Z"))
+            )
+        );
+    }
+
+    #[test]
+    fn until_without_since() {
+        let range = range(None, Some("01:00"));
+        assert_eq!(
+            range.bounds(utc("2025-08-28T04:00:00Z")),
+            (None, Some(utc("2025-08-28T01:00:00Z")))
+        );
+    }
+
+    #[test]
+    fn absolute_bounds() {
+        let range = range(Some("2025-08-28 04:50"), Some("2025-08-28"));
+        let expected = (
+            Some(utc("2025-08-28T04:50:00Z")),
+            Some(utc("2025-08-28T00:00:00Z")),
+        );
+        assert_eq!(range.absolute(), expected);
+        assert_eq!(range.bounds(utc("2020-01-01T00:00:00Z")), expected);
+        // Only a time of day moves to the next day, so this range is empty
+        assert!(!range.contains(utc("2025-08-28T04:50:00Z")));
+    }
+
+    #[test]
+    fn bounds_are_resolved_once() {
+        let range = range(Some("05:00"), None);
+        let first = range.bounds(utc("2025-08-28T04:00:00Z"));
+        assert_eq!(range.bounds(utc("2025-08-29T04:00:00Z")), first);
+    }
+
+    #[test]
+    fn unbounded() {
+        let range = range(None, None);
+        assert!(range.is_unbounded());
+        assert!(range.contains(utc("2025-08-28T04:00:00Z")));
+    }
+}
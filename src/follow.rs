@@ -0,0 +1,109 @@
+//! Following of a growing log file, as with `tail -F`.
+//!
+//! Once the end of the file is reached we poll for new content. If the file is
+//! truncated we start again from the beginning, and if it is replaced by a new file
+//! (a different inode), as happens when logs are rotated, we reopen it by name.
+//!
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom};
+use std::os::unix::fs::MetadataExt;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Iterator over the lines of a file which never ends.
+///
+/// When the end of the file is reached, a single `WouldBlock` error is returned to
+/// signal that the lines so far can be written out before waiting for more.
+pub struct FollowLines {
+    path: PathBuf,
+    reader: BufReader<File>,
+    inode: u64,
+    position: u64,
+    /// A line which has not yet had its newline written
+    partial: Vec<u8>,
+    idle: bool,
+}
+
+impl FollowLines {
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let file = File::open(&path)?;
+        let inode = file.metadata()?.ino();
+        Ok(Self {
+            path,
+            reader: BufReader::new(file),
+            inode,
+            position: 0,
+            partial: Vec::new(),
+            idle: false,
+        })
+    }
+
+    /// Reopen or rewind the file if it has been rotated or truncated.
+    ///
+    /// Returns whether the file changed.
+    fn check_rotation(&mut self) -> io::Result<bool> {
+        // The file may have been moved before its replacement is created
+        let Ok(metadata) = fs::metadata(&self.path) else {
+            return Ok(false);
+        };
+        if metadata.ino() != self.inode {
+            let file = File::open(&self.path)?;
+            self.inode = file.metadata()?.ino();
+            self.reader = BufReader::new(file);
+        } else if metadata.len() < self.position {
+            self.reader.seek(SeekFrom::Start(0))?;
+        } else {
+            return Ok(false);
+        }
+        self.position = 0;
+        Ok(true)
+    }
+
+    fn take_line(&mut self) -> String {
+        let mut line = std::mem::take(&mut self.partial);
+        if line.last() == Some(&b'\n') {
+            line.pop();
+        }
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        String::from_utf8_lossy(&line).into_owned()
+    }
+}
+
+impl Iterator for FollowLines {
+    type Item = io::Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let read = match self.reader.read_until(b'\n', &mut self.partial) {
+                Ok(read) => read,
+                Err(e) => return Some(Err(e)),
+            };
+            self.position += read as u64;
+            if self.partial.last() == Some(&b'\n') {
+                self.idle = false;
+                return Some(Ok(self.take_line()));
+            }
+            if read > 0 {
+                continue;
+            }
+
+            if !self.idle {
+                self.idle = true;
+                return Some(Err(io::ErrorKind::WouldBlock.into()));
+            }
+            thread::sleep(POLL_INTERVAL);
+            match self.check_rotation() {
+                // The unterminated end of the old file is still a line
+                Ok(true) if !self.partial.is_empty() => return Some(Ok(self.take_line())),
+                Ok(_) => (),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
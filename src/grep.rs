@@ -0,0 +1,117 @@
+//! Searching for records with a regex, showing surrounding records as context.
+//!
+//! Matching is done per record rather than per line, so a multi-line record is
+//! shown whole when any of its lines match, and context is counted in records. Only
+//! the message and continuation lines are searched, so that the timestamp, level and
+//! target neither match nor depend on how they are displayed.
+//!
+use std::collections::VecDeque;
+use std::ops::Range;
+
+use regex::Regex;
+
+use crate::record::Record;
+
+/// What to write for each record passed to [`Grep::push`]
+pub enum GrepOutput<T> {
+    /// A gap between groups of matches and their context
+    Separator,
+    Match(T),
+    Context(T),
+}
+
+pub struct Grep<T> {
+    regex: Regex,
+    before: usize,
+    after: usize,
+    /// Records which may be needed as context for the next match
+    history: VecDeque<T>,
+    after_remaining: usize,
+    /// Whether anything has been written, and whether records have since been skipped
+    written: bool,
+    skipped: bool,
+}
+
+impl<T> Grep<T> {
+    pub fn new(regex: Regex, before: usize, after: usize) -> Self {
+        Self {
+            regex,
+            before,
+            after,
+            history: VecDeque::with_capacity(before),
+            after_remaining: 0,
+            written: false,
+            skipped: false,
+        }
+    }
+
+    pub fn regex(&self) -> &Regex {
+        &self.regex
+    }
+
+    pub fn is_match(&self, record: &Record) -> bool {
+        searched_lines(record)
+            .iter()
+            .any(|line| self.regex.is_match(line))
+    }
+
+    /// Take the next record, returning the records which should now be written
+    pub fn push(&mut self, item: T, is_match: bool) -> Vec<GrepOutput<T>> {
+        let mut output = Vec::new();
+        if is_match {
+            if self.written && self.skipped {
+                output.push(GrepOutput::Separator);
+            }
+            output.extend(self.history.drain(..).map(GrepOutput::Context));
+            output.push(GrepOutput::Match(item));
+            self.after_remaining = self.after;
+            self.written = true;
+            self.skipped = false;
+        } else if self.after_remaining > 0 {
+            self.after_remaining -= 1;
+            output.push(GrepOutput::Context(item));
+        } else if self.before > 0 {
+            if self.history.len() == self.before {
+                self.history.pop_front();
+                self.skipped = true;
+            }
+            self.history.push_back(item);
+        } else {
+            self.skipped = true;
+        }
+        output
+    }
+}
+
+/// The lines of a record which are searched: the message and any continuation lines,
+/// or only the continuation lines if the line has already been written. A line which
+/// failed to parse is searched whole.
+fn searched_lines(record: &Record) -> Vec<&str> {
+    let mut lines = Vec::with_capacity(record.continuation.len() + 1);
+    if !record.line_shown {
+        let start = record.format.map_or(0, |format| format.path_end);
+        lines.push(&record.line[start..]);
+    }
+    lines.extend(record.continuation.iter().map(String::as_str));
+    lines
+}
+
+/// The matches of the regex in a record, as ranges of its searched lines joined by
+/// newlines, along with the length of that text. The searched text ends the record
+/// as it is written, so the ranges can be found from the end.
+pub fn find_matches(regex: &Regex, record: &Record) -> (Vec<Range<usize>>, usize) {
+    let mut matches = Vec::new();
+    let mut offset = 0;
+    for (i, line) in searched_lines(record).iter().enumerate() {
+        if i > 0 {
+            offset += 1;
+        }
+        matches.extend(
+            regex
+                .find_iter(line)
+                .map(|m| m.start() + offset..m.end() + offset),
+        );
+        offset += line.len();
+    }
+    (matches, offset)
+}
@@ -0,0 +1,177 @@
+//! A chart of the records over time, for `log histogram`.
+//!
+//! Each row is a bucket of time, with a bar of the records in it stacked by level from
+//! ERROR on the left to TRACE on the right. Buckets without records are still drawn,
+//! so that a silence is as visible as a burst.
+//!
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+use crate::parse::LogType;
+use crate::record::Record;
+use crate::theme::Theme;
+use crate::time;
+
+/// The blocks each level is drawn with. Without colour the levels are told apart by
+/// their shade instead.
+const BLOCK: char = '█';
+const SHADES: [char; 5] = ['█', '▓', '▒', '░', '·'];
+
+/// The most rows drawn, as empty buckets are drawn too and a bucket much smaller than
+/// the log would write them without end
+const MAX_ROWS: i64 = 10_000;
+
+pub struct Histogram {
+    bucket: TimeDelta,
+    /// The records per level, by the start of their bucket in microseconds
+    counts: BTreeMap<i64, [usize; 5]>,
+}
+
+impl Histogram {
+    pub fn new(bucket: TimeDelta) -> Self {
+        Self {
+            bucket,
+            counts: BTreeMap::new(),
+        }
+    }
+
+    fn bucket_micros(&self) -> i64 {
+        self.bucket.num_microseconds().unwrap_or(i64::MAX).max(1)
+    }
+
+    /// Count a record, if it has a timestamp
+    pub fn push(&mut self, record: &Record) {
+        let (Some(format), Some(timestamp)) = (record.format, record.timestamp()) else {
+            return;
+        };
+        if record.line_shown {
+            return;
+        }
+        let bucket = self.bucket_micros();
+        let start = timestamp.timestamp_micros().div_euclid(bucket) * bucket;
+        self.counts.entry(start).or_default()[format.log_type as usize] += 1;
+    }
+
+    /// Draw the chart to fit within `width` columns, failing if the bucket is too small
+    /// for the time covered
+    pub fn render(&self, width: usize, theme: &Theme, colour: bool) -> Result<Vec<String>, String> {
+        let (Some(first), Some(last)) = (
+            self.counts.first_key_value().map(|(start, _)| *start),
+            self.counts.last_key_value().map(|(start, _)| *start),
+        ) else {
+            return Ok(vec!["No records with timestamps".to_string()]);
+        };
+        let bucket = self.bucket_micros();
+        let rows = (last - first) / bucket + 1;
+        if rows > MAX_ROWS {
+            return Err(format!(
+                "A bucket of {} would draw {rows} rows, use a bucket of at least {}",
+                time::format_delta(self.bucket),
+                smallest_bucket((last - first + bucket) as u64 / MAX_ROWS as u64 + 1)
+            ));
+        }
+        let block = |i: usize| if colour { BLOCK } else { SHADES[i] };
+
+        let label_format = self.label_format(first, last);
+        let max_total = self
+            .counts
+            .values()
+            .map(|counts| counts.iter().sum::<usize>())
+            .max()
+            .unwrap_or(0);
+        let count_width = max_total.to_string().len();
+        let label_width = format_start(first, &label_format).chars().count();
+        let bar_width = width.saturating_sub(label_width + count_width + 2).max(10);
+        let scale = bar_width as f64 / max_total.max(1) as f64;
+
+        let mut legend = String::new();
+        for (i, log_type) in LogType::ALL.iter().enumerate() {
+            legend.push_str(&format!(
+                "{}{} {}{}  ",
+                theme.level(*log_type),
+                block(i),
+                log_type.as_str(),
+                theme.reset
+            ));
+        }
+        let mut lines = vec![legend.trim_end().to_string(), String::new()];
+
+        let mut start = first;
+        while start <= last {
+            let counts = self.counts.get(&start).copied().unwrap_or_default();
+            let mut bar = String::new();
+            let mut drawn = 0;
+            let mut cumulative = 0;
+            for (i, count) in counts.iter().enumerate() {
+                cumulative += count;
+                let end = (cumulative as f64 * scale).round() as usize;
+                // A few records must not disappear next to a large bar
+                let cells = match end.saturating_sub(drawn) {
+                    0 if *count > 0 => 1,
+                    cells => cells,
+                };
+                if cells > 0 {
+                    bar.push_str(theme.level(LogType::ALL[i]));
+                    bar.extend(std::iter::repeat_n(block(i), cells));
+                    bar.push_str(&theme.reset);
+                }
+                drawn += cells;
+            }
+            let total = counts.iter().sum::<usize>();
+            lines.push(format!(
+                "{}{}{} {bar}{} {total:>count_width$}",
+                theme.timestamp,
+                format_start(start, &label_format),
+                theme.reset,
+                " ".repeat(bar_width.saturating_sub(drawn)),
+            ));
+            start += bucket;
+        }
+        Ok(lines)
+    }
+
+    /// The shortest format which tells the buckets apart
+    fn label_format(&self, first: i64, last: i64) -> String {
+        if self.bucket >= TimeDelta::days(1) {
+            return "%Y-%m-%d".to_string();
+        }
+        let time = if self.bucket < TimeDelta::seconds(1) {
+            "%H:%M:%S%.3f"
+        } else if self.bucket < TimeDelta::minutes(1) {
+            "%H:%M:%S"
+        } else {
+            "%H:%M"
+        };
+        let date = |micros| DateTime::<Utc>::from_timestamp_micros(micros).map(|t| t.date_naive());
+        if date(first) == date(last) {
+            time.to_string()
+        } else {
+            format!("%m-%d {time}")
+        }
+    }
+}
+
+/// The smallest bucket of a whole number of units which is at least `micros` long,
+/// as it would be written on the command line
+fn smallest_bucket(micros: u64) -> String {
+    let units = [
+        (3_600_000_000, "h"),
+        (60_000_000, "m"),
+        (1_000_000, "s"),
+        (1_000, "ms"),
+    ];
+    let (unit, suffix) = units
+        .into_iter()
+        .rev()
+        .find(|(unit, _)| micros.div_ceil(*unit) < 1000)
+        .unwrap_or(units[0]);
+    format!("{}{suffix}", micros.div_ceil(unit))
+}
+
+fn format_start(start: i64, format: &str) -> String {
+    DateTime::<Utc>::from_timestamp_micros(start)
+        .unwrap_or_default()
+        .format(format)
+        .to_string()
+}
@@ -0,0 +1,174 @@
+//! Sources of log lines.
+//!
+//! Files compressed with gzip, zstd or xz, as rotated logs are often archived, are
+//! detected by their magic bytes and decompressed as they are read.
+//!
+//! Plain files can be positioned at a start time by binary searching on the
+//! timestamps, rather than reading every record before it.
+//!
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use flate2::bufread::MultiGzDecoder;
+use xz2::bufread::XzDecoder;
+
+use crate::{json, parse, time};
+
+pub type Lines = Box<dyn Iterator<Item = io::Result<String>>>;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const XZ_MAGIC: &[u8] = &[0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00];
+
+/// Once the search has narrowed to this many bytes, the rest are read through
+const SEEK_RESOLUTION: u64 = 64 * 1024;
+/// Number of lines to read looking for a timestamp, eg. through a backtrace
+const MAX_LINES_SCANNED: usize = 1000;
+
+pub enum InputSource {
+    File(BufReader<File>),
+    Gzip(BufReader<MultiGzDecoder<BufReader<File>>>),
+    Zstd(BufReader<zstd::Decoder<'static, BufReader<File>>>),
+    Xz(BufReader<XzDecoder<BufReader<File>>>),
+    Pipe(io::StdinLock<'static>),
+}
+
+impl InputSource {
+    /// Open a file, decompressing it if it starts with a known magic number
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let start = reader.fill_buf()?;
+        Ok(if start.starts_with(GZIP_MAGIC) {
+            Self::Gzip(BufReader::new(MultiGzDecoder::new(reader)))
+        } else if start.starts_with(ZSTD_MAGIC) {
+            Self::Zstd(BufReader::new(zstd::Decoder::with_buffer(reader)?))
+        } else if start.starts_with(XZ_MAGIC) {
+            Self::Xz(BufReader::new(XzDecoder::new_multi_decoder(reader)))
+        } else {
+            Self::File(reader)
+        })
+    }
+
+    /// The timestamp of the first record of a file, reading through any compression
+    pub fn first_timestamp(path: impl AsRef<Path>) -> io::Result<Option<DateTime<Utc>>> {
+        let mut source = Self::open(path)?;
+        Ok(scan_for_timestamp(&mut source)?.map(|(_, timestamp)| timestamp))
+    }
+
+    /// Move a plain file to the last record before `since`, which is given the
+    /// timestamp of the first record. Other sources are left at the start.
+    pub fn seek_to_time(
+        &mut self,
+        since: impl FnOnce(DateTime<Utc>) -> Option<DateTime<Utc>>,
+    ) -> io::Result<()> {
+        let Self::File(reader) = self else {
+            return Ok(());
+        };
+        let start = match next_timestamp(reader, 0)? {
+            Some((_, first)) => match since(first) {
+                Some(since) if first < since => search_for_time(reader, since)?,
+                _ => 0,
+            },
+            None => 0,
+        };
+        reader.seek(SeekFrom::Start(start))?;
+        Ok(())
+    }
+}
+
+/// Binary search for the offset of a record shortly before `since`
+fn search_for_time(reader: &mut BufReader<File>, since: DateTime<Utc>) -> io::Result<u64> {
+    // The record found from `low` is always before `since`
+    let mut low = 0;
+    let mut high = reader.get_ref().metadata()?.len();
+    while high - low > SEEK_RESOLUTION {
+        let mid = low + (high - low) / 2;
+        match next_timestamp(reader, mid)? {
+            Some((_, timestamp)) if timestamp < since => low = mid,
+            _ => high = mid,
+        }
+    }
+    Ok(next_timestamp(reader, low)?.map_or(0, |(offset, _)| offset))
+}
+
+/// Find the first line after `offset` which parses with a timestamp, returning the
+/// offset of the line along with the timestamp
+fn next_timestamp(
+    reader: &mut BufReader<File>,
+    offset: u64,
+) -> io::Result<Option<(u64, DateTime<Utc>)>> {
+    reader.seek(SeekFrom::Start(offset))?;
+    let mut position = offset;
+    if offset > 0 {
+        // Skip the rest of the line we landed in
+        position += reader.read_until(b'\n', &mut Vec::new())? as u64;
+    }
+    Ok(scan_for_timestamp(reader)?.map(|(offset, timestamp)| (position + offset, timestamp)))
+}
+
+/// Read lines until one parses with a timestamp, returning the offset of the line from
+/// where reading started along with the timestamp
+fn scan_for_timestamp(reader: &mut impl BufRead) -> io::Result<Option<(u64, DateTime<Utc>)>> {
+    let mut position = 0;
+    let mut line = Vec::new();
+    for _ in 0..MAX_LINES_SCANNED {
+        line.clear();
+        let read = reader.read_until(b'\n', &mut line)?;
+        if read == 0 {
+            break;
+        }
+        let text = String::from_utf8_lossy(&line);
+        let text = text.trim_end();
+        let (text, format) = match json::normalise_line(text) {
+            Some((text, format)) => (text, Some(format)),
+            None => (text.to_string(), parse::parse_line_from_scratch(text)),
+        };
+        if let Some(format) = format
+            && let Some(timestamp) = time::parse_timestamp(&text[format.tz_start..format.tz_end])
+        {
+            return Ok(Some((position, timestamp)));
+        }
+        position += read as u64;
+    }
+    Ok(None)
+}
+
+/// Whether the file starts with the magic number of a supported compression format
+pub fn is_compressed(path: impl AsRef<Path>) -> io::Result<bool> {
+    Ok(!matches!(InputSource::open(path)?, InputSource::File(_)))
+}
+
+impl Read for InputSource {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::File(f) => f.read(buf),
+            Self::Gzip(f) => f.read(buf),
+            Self::Zstd(f) => f.read(buf),
+            Self::Xz(f) => f.read(buf),
+            Self::Pipe(p) => p.read(buf),
+        }
+    }
+}
+
+impl BufRead for InputSource {
+    fn consume(&mut self, amount: usize) {
+        match self {
+            Self::File(f) => f.consume(amount),
+            Self::Gzip(f) => f.consume(amount),
+            Self::Zstd(f) => f.consume(amount),
+            Self::Xz(f) => f.consume(amount),
+            Self::Pipe(p) => p.consume(amount),
+        }
+    }
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        match self {
+            Self::File(f) => f.fill_buf(),
+            Self::Gzip(f) => f.fill_buf(),
+            Self::Zstd(f) => f.fill_buf(),
+            Self::Xz(f) => f.fill_buf(),
+            Self::Pipe(p) => p.fill_buf(),
+        }
+    }
+}
@@ -0,0 +1,151 @@
+//! Support for logs written by `tracing_subscriber::fmt().json()`, eg.
+//!
+//! {"timestamp":"2025-08-28T04:57:18.797136Z","level":"INFO","fields":{"message":"hi"},"target":"crate::path"}
+//!
+//! Rather than colouring JSON lines separately, we rewrite them into the default
+//! text format, `TIMESTAMP LOG_LEVEL SPANS: SOURCE: LOG_MESSAGE key=value`, so that
+//! they can be coloured exactly as the text logs are. The JSON says which part is
+//! which, so the offsets of each part are returned along with the line rather than
+//! being guessed from the text.
+//!
+use serde_json::{Map, Value};
+
+use crate::parse::{LineFormat, LogType};
+
+/// Keys of the top level JSON object which are not event fields
+const RESERVED_KEYS: [&str; 6] = ["timestamp", "level", "target", "fields", "span", "spans"];
+
+/// Rewrite a JSON formatted log line into the default text format, along with the
+/// format of the rewritten line.
+///
+/// Returns None if the line is not a JSON object with a timestamp and a level.
+pub fn normalise_line(line: &str) -> Option<(String, LineFormat)> {
+    let trimmed = line.trim();
+    if !trimmed.starts_with('{') {
+        return None;
+    }
+    let object: Map<String, Value> = serde_json::from_str(trimmed).ok()?;
+    let timestamp = object.get("timestamp")?.as_str()?;
+    let level = object.get("level")?.as_str()?;
+    let log_type = LogType::parse(level)?;
+
+    let mut new_line = String::with_capacity(line.len());
+    new_line.push_str(timestamp);
+    new_line.push(' ');
+    let level_start = new_line.len();
+    new_line.push_str(&format!("{:<5}", level));
+    let level_word_end = new_line.len();
+
+    // `spans` holds the full context from the root, `span` only the current span
+    let spans = match (object.get("spans"), object.get("span")) {
+        (Some(Value::Array(spans)), _) => spans.iter().collect(),
+        (_, Some(span)) => vec![span],
+        _ => Vec::new(),
+    };
+    let mut spans_end = None;
+    if !spans.is_empty() {
+        new_line.push(' ');
+        for (i, span) in spans.iter().enumerate() {
+            if i > 0 {
+                new_line.push(':');
+            }
+            push_span(&mut new_line, span);
+        }
+        new_line.push(':');
+        spans_end = Some(new_line.len());
+    }
+
+    let mut path = None;
+    if let Some(target) = object.get("target").and_then(Value::as_str) {
+        new_line.push(' ');
+        let path_start = new_line.len();
+        new_line.push_str(target);
+        new_line.push(':');
+        path = Some((path_start, new_line.len()));
+    }
+
+    // Events are nested under `fields` unless `flatten_event(true)` was used
+    let fields = match object.get("fields") {
+        Some(Value::Object(fields)) => fields
+            .iter()
+            .chain(
+                object
+                    .iter()
+                    .filter(|(key, _)| !RESERVED_KEYS.contains(&key.as_str())),
+            )
+            .collect::<Vec<_>>(),
+        _ => object
+            .iter()
+            .filter(|(key, _)| !RESERVED_KEYS.contains(&key.as_str()))
+            .collect(),
+    };
+
+    if let Some((_, message)) = fields.iter().find(|(key, _)| key.as_str() == "message") {
+        new_line.push(' ');
+        match message {
+            Value::String(message) => new_line.push_str(message),
+            other => new_line.push_str(&other.to_string()),
+        }
+    }
+    for (key, value) in fields.iter().filter(|(key, _)| key.as_str() != "message") {
+        new_line.push(' ');
+        push_field(&mut new_line, key, value);
+    }
+
+    // As for text lines, the level includes the spaces after it, and the spans include
+    // the space before a target
+    let level_end = new_line.len() - new_line[level_word_end..].trim_start_matches(' ').len();
+    let spans_end = match (spans_end, path) {
+        (Some(_), Some((path_start, _))) => path_start,
+        (Some(spans_end), None) => spans_end,
+        (None, _) => level_end,
+    };
+    let (path_start, path_end) = path.unwrap_or((spans_end, spans_end));
+    let format = LineFormat {
+        log_type,
+        tz_start: 0,
+        tz_end: level_start,
+        level_start,
+        level_end,
+        spans_start: level_end,
+        spans_end,
+        path_start,
+        path_end,
+    };
+    Some((new_line, format))
+}
+
+/// Write a span as `name{key=value key=value}`, omitting the braces if there are no fields
+fn push_span(new_line: &mut String, span: &Value) {
+    let Value::Object(span) = span else {
+        return;
+    };
+    if let Some(name) = span.get("name").and_then(Value::as_str) {
+        new_line.push_str(name);
+    }
+    let mut fields = span
+        .iter()
+        .filter(|(key, _)| key.as_str() != "name")
+        .peekable();
+    if fields.peek().is_none() {
+        return;
+    }
+    new_line.push('{');
+    for (i, (key, value)) in fields.enumerate() {
+        if i > 0 {
+            new_line.push(' ');
+        }
+        push_field(new_line, key, value);
+    }
+    new_line.push('}');
+}
+
+/// Write a field as `key=value`, quoting strings as the text formatter does
+fn push_field(new_line: &mut String, key: &str, value: &Value) {
+    new_line.push_str(key);
+    new_line.push('=');
+    match value {
+        Value::String(s) => new_line.push_str(&format!("{:?}", s)),
+        other => new_line.push_str(&other.to_string()),
+    }
+}
@@ -3,96 +3,552 @@
 //! 2025-08-28T04:57:18.797136Z INFO  crate::path::file: I am the log message
 //!
 //! We assume that the TIMESTAMP will be of constant length within a set of logs,
-//! that the LOG_LEVEL will of length 5, padded with spaces at the end and that the
-//! SOURCE and LOG_MESSAGE will vary in length. As such, we parse the first log that
-//! comes in fully, before reusing the indices for the TIMESTAMP, LOG_LEVEL and start
-//! of SOURCE and simply parsing the rest of the string from there.
+//! and that the LOG_LEVEL, SOURCE and LOG_MESSAGE will vary in length. As such, we
+//! parse the first log that comes in fully, before reusing the indices for the
+//! TIMESTAMP and start of LOG_LEVEL and simply parsing the rest of the string from
+//! there. A span context may appear between the LOG_LEVEL and SOURCE, see [`parse`].
 //!
+//! Lines written by the JSON formatter are first rewritten into the above format.
+//!
+mod ansi;
+mod config;
+mod dedupe;
+mod filter;
+mod follow;
+mod grep;
+mod histogram;
+mod input;
+mod json;
+mod merge;
+mod output;
+mod parse;
+mod patterns;
+mod record;
+mod rolling;
+mod spans;
+mod stats;
+mod theme;
+mod time;
+mod tui;
+
+use std::env;
 use std::ffi::c_int;
-use std::fs::{self, File};
+use std::fmt::Display;
 use std::io::{self, BufRead, Write};
-use std::process::{Child, Command, Stdio, exit};
+use std::path::{Path, PathBuf};
+use std::process::exit;
+
+use chrono::TimeDelta;
+use clap::{ArgGroup, Parser, Subcommand, ValueEnum};
+use regex::Regex;
+use serde::Deserialize;
 
-use clap::{Parser, command};
+use config::{Config, Preset};
+use dedupe::{Dedupe, DedupeMode};
+use filter::{Filter, TargetFilter, TimeRange};
+use follow::FollowLines;
+use grep::{Grep, GrepOutput};
+use histogram::Histogram;
+use input::{InputSource, Lines};
+use merge::Merge;
+use output::WriteDestination;
+use parse::{LineFormat, LogType};
+use patterns::Patterns;
+use record::{Record, Records, Repeats};
+use spans::SpanTree;
+use stats::Stats;
+use theme::{ColourDepth, TargetColours, Theme, ThemeName, ThemeStyles};
+use time::{TimeArg, TimeFormat, TimestampDisplay, TimestampMode, Zone};
 
 unsafe extern "C" {
     fn isatty(fd: c_int) -> c_int;
 }
 const STDIN_FILENO: c_int = 0;
+const STDOUT_FILENO: c_int = 1;
 
-/// Recolour tracing logs and view them in less. Supports piping of input and output
+/// Recolour tracing logs and view them in a pager. Supports piping of input and output
 #[derive(Parser, Debug)]
-#[command(author, version, about)]
+#[command(author, version, about, args_conflicts_with_subcommands = true)]
+#[command(group(ArgGroup::new("search").args(["grep", "preset"]).multiple(true)))]
 struct Args {
-    /// The log file to parse
-    file: Option<String>,
+    #[command(subcommand)]
+    command: Option<Command>,
 
-    /// Output directly to stdout for piping rather than opening less
+    #[command(flatten)]
+    input: InputArgs,
+
+    /// Output directly to stdout for piping rather than opening the pager. This is
+    /// the default when stdout is not a terminal
     #[arg(short = 'P', long = "pipe")]
     pipe: bool,
 
-    /// Arguments to pass directly to less (use -- to separate)
-    #[arg(trailing_var_arg = true)]
-    less_args: Vec<String>,
+    /// When to colour the output. With auto, colour is only used for a terminal and
+    /// when NO_COLOR is not set [default: auto]
+    #[arg(long = "color", value_enum, global = true)]
+    color: Option<ColorChoice>,
+
+    /// The colour theme [default: dark]
+    #[arg(long = "theme", value_enum, global = true)]
+    theme: Option<ThemeName>,
+
+    /// Colour each target by its name, or by the name of its crate [default: off]
+    #[arg(long = "target-colors", alias = "target-colours", value_enum)]
+    target_colors: Option<TargetColours>,
+
+    /// How to show the timestamp of each record [default: absolute]
+    #[arg(long = "timestamps", value_enum)]
+    timestamps: Option<TimestampMode>,
+
+    /// Show absolute timestamps in this time zone: local, a name such as
+    /// Australia/Brisbane, or an offset such as +10:00
+    #[arg(long = "tz", value_parser = time::parse_zone, allow_hyphen_values = true)]
+    tz: Option<Zone>,
+
+    /// Show absolute timestamps in this strftime format, eg. '%H:%M:%S%.3f'
+    #[arg(long = "time-format", value_parser = time::parse_time_format)]
+    time_format: Option<TimeFormat>,
+
+    /// Apply the level, filter and grep of a preset from the config file. Options
+    /// given on the command line take precedence
+    #[arg(long = "preset")]
+    preset: Option<String>,
+
+    /// View the logs in the built-in full-screen viewer rather than the pager
+    #[arg(long = "tui", conflicts_with_all = ["pipe", "follow", "grep"])]
+    tui: bool,
+
+    /// Keep reading as the file grows, reopening it if it is rotated
+    #[arg(
+        short = 'f',
+        long = "follow",
+        requires = "files",
+        conflicts_with = "dir"
+    )]
+    follow: bool,
+
+    /// Fold each run of records with the same level, target and message into one,
+    /// optionally ignoring the numbers in the message
+    #[arg(
+        long = "dedupe",
+        value_enum,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "exact"
+    )]
+    dedupe: Option<DedupeMode>,
+
+    /// Only show records whose message matches this regex, highlighting the matches
+    #[arg(short = 'g', long = "grep")]
+    grep: Option<Regex>,
+
+    /// Records of context to show after each match
+    #[arg(short = 'A', long = "after-context", requires = "search")]
+    after_context: Option<usize>,
+
+    /// Records of context to show before each match
+    #[arg(short = 'B', long = "before-context", requires = "search")]
+    before_context: Option<usize>,
+
+    /// Records of context to show before and after each match
+    #[arg(
+        short = 'C',
+        long = "context",
+        requires = "search",
+        default_value_t = 0
+    )]
+    context: usize,
+
+    /// Arguments to pass directly to the pager (use -- to separate), replacing those
+    /// of the config file
+    #[arg(last = true)]
+    pager_args: Vec<String>,
+}
+
+/// The logs to read and the records to select from them
+#[derive(clap::Args, Debug)]
+struct InputArgs {
+    /// The log files to parse. Several files are merged in timestamp order
+    files: Vec<String>,
+
+    /// Read the files written by a tracing-appender rolling appender in this directory
+    #[arg(long = "dir", conflicts_with = "files")]
+    dir: Option<PathBuf>,
+
+    /// The file name prefix used by the rolling appender
+    #[arg(long = "prefix", requires = "dir", default_value = "")]
+    prefix: String,
+
+    /// Only show records at or above this level
+    #[arg(short = 'l', long = "level", value_enum, conflicts_with = "only")]
+    level: Option<LogType>,
+
+    /// Only show records at exactly these levels, eg. warn,error
+    #[arg(long = "only", value_enum, value_delimiter = ',')]
+    only: Vec<LogType>,
+
+    /// Only show records enabled by these EnvFilter directives, eg. my_crate=debug,hyper=warn
+    #[arg(long = "filter")]
+    filter: Option<TargetFilter>,
+
+    /// Only show records at or after this time, eg. 2025-08-28T04:50Z, 04:50 or 15m (ago)
+    #[arg(long = "since", value_parser = time::parse_time_arg)]
+    since: Option<TimeArg>,
+
+    /// Only show records at or before this time, in the same forms as --since
+    #[arg(long = "until", value_parser = time::parse_time_arg)]
+    until: Option<TimeArg>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Summarise the logs rather than showing them
+    ///
+    /// Reports the records per level, the busiest targets, the time covered and the
+    /// lines which failed to parse
+    Stats {
+        #[command(flatten)]
+        input: InputArgs,
+
+        /// The number of targets to list
+        #[arg(long = "top", default_value_t = 10)]
+        top: usize,
+    },
+    /// Chart the records over time, stacked by level
+    Histogram {
+        #[command(flatten)]
+        input: InputArgs,
+
+        /// The time covered by each bar, eg. 10s, 1m or 1h
+        #[arg(long = "bucket", value_parser = time::parse_interval, default_value = "1m")]
+        bucket: TimeDelta,
+
+        /// The width of the chart [default: the width of the terminal, or 80]
+        #[arg(long = "width")]
+        width: Option<usize>,
+    },
+    /// Show the tree of spans with their timings, from span events
+    ///
+    /// Requires the span events of tracing-subscriber's FmtSpan, at least CLOSE, which
+    /// give the busy and idle time of each span
+    Spans {
+        #[command(flatten)]
+        input: InputArgs,
+
+        /// The number of the slowest spans to list for each name
+        #[arg(long = "top", default_value_t = 3)]
+        top: usize,
+    },
+    /// Group the messages into templates, masking the parts which vary
+    ///
+    /// Lists each template with its level, target, count and first and last
+    /// occurrence, most common first
+    Patterns {
+        #[command(flatten)]
+        input: InputArgs,
+
+        /// The number of templates to list [default: all]
+        #[arg(long = "top")]
+        top: Option<usize>,
+    },
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl InputArgs {
+    /// Select records with these arguments, taking the levels and target filter from
+    /// the preset where they are not given
+    fn filter(&mut self, preset: Preset) -> Filter {
+        // The level and exact levels replace each other, so neither is taken from the
+        // preset if either is given
+        let (min_level, only) = if self.level.is_some() || !self.only.is_empty() {
+            (self.level, std::mem::take(&mut self.only))
+        } else {
+            (preset.level, preset.only)
+        };
+        let targets = self.filter.take().or_else(|| {
+            preset
+                .filter
+                .map(|directives| directives.parse().unwrap_or_else(|e| fail(e)))
+        });
+        Filter {
+            min_level,
+            only,
+            targets,
+            time_range: TimeRange::new(self.since, self.until),
+        }
+    }
+
+    /// Open each source of records, skipping ahead to the start of the time range
+    /// where the source can seek. Exits naming the input if one cannot be opened.
+    fn open(&self, filter: &Filter, follow: bool) -> Vec<Records<Lines>> {
+        let since = |first| filter.time_range.bounds(first).0;
+        let mut sources: Vec<Records<Lines>> = Vec::new();
+        if let Some(dir) = &self.dir {
+            let (start, end) = filter.time_range.absolute();
+            let files = rolling::find_rolled_files(dir, &self.prefix)
+                .unwrap_or_else(|e| fail_input(dir, e));
+            if files.is_empty() {
+                match self.prefix.as_str() {
+                    "" => eprintln!(
+                        "No rolled log files named DATE in {}, set the file name prefix with --prefix",
+                        dir.display()
+                    ),
+                    prefix => eprintln!(
+                        "No rolled log files named {prefix}.DATE in {}",
+                        dir.display()
+                    ),
+                }
+                exit(1)
+            }
+            let files = files
+                .into_iter()
+                .filter(|file| file.overlaps(start, end))
+                .collect();
+            // Each file is named in its own error
+            let lines = rolling::lines(files, since).unwrap_or_else(|e| fail(e));
+            sources.push(Records::new(lines));
+        } else if self.files.is_empty() {
+            let is_a_tty = unsafe { isatty(STDIN_FILENO) == 1 };
+            if is_a_tty {
+                eprintln!("Missing filename");
+                exit(1)
+            }
+            sources.push(Records::new(Box::new(
+                InputSource::Pipe(io::stdin().lock()).lines(),
+            )));
+        } else if follow {
+            if self.files.len() > 1 {
+                eprintln!("Only a single file can be followed");
+                exit(1)
+            }
+            let file = &self.files[0];
+            if input::is_compressed(file).unwrap_or_else(|e| fail_input(file, e)) {
+                eprintln!("Compressed files cannot be followed");
+                exit(1)
+            }
+            let lines = FollowLines::open(file).unwrap_or_else(|e| fail_input(file, e));
+            sources.push(Records::new(Box::new(lines)));
+        } else {
+            // Times of day are on the date of the earliest record of any of the files,
+            // which must be known before any of them can be moved to the start time
+            if !filter.time_range.is_unbounded() {
+                let first = self
+                    .files
+                    .iter()
+                    .filter_map(|file| {
+                        InputSource::first_timestamp(file).unwrap_or_else(|e| fail_input(file, e))
+                    })
+                    .min();
+                if let Some(first) = first {
+                    filter.time_range.bounds(first);
+                }
+            }
+            for file in &self.files {
+                let mut source = InputSource::open(file).unwrap_or_else(|e| fail_input(file, e));
+                source
+                    .seek_to_time(since)
+                    .unwrap_or_else(|e| fail_input(file, e));
+                sources.push(Records::new(Box::new(source.lines())));
+            }
+        }
+        sources
+    }
 }
 
 fn main() -> io::Result<()> {
-    let args = Args::parse();
+    let mut args = Args::parse();
+    let config = Config::load().unwrap_or_else(|e| fail(e));
 
-    let reader = if let Some(file) = args.file {
-        let file = File::open(&file)?;
-        InputSource::File(io::BufReader::new(file))
+    let to_terminal = unsafe { isatty(STDOUT_FILENO) == 1 };
+    let colour = match args.color.or(config.color).unwrap_or(ColorChoice::Auto) {
+        ColorChoice::Auto => to_terminal && env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()),
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+    };
+    let mut styles = ThemeStyles::builtin(args.theme.or(config.theme).unwrap_or_default());
+    for (component, style) in &config.styles {
+        let style = style
+            .parse()
+            .unwrap_or_else(|e| fail(format!("Invalid style for {component}: {e}")));
+        styles.set(component, style).unwrap_or_else(|e| fail(e));
+    }
+    let mut theme = if colour {
+        Theme::new(&styles, ColourDepth::detect())
     } else {
-        let is_a_tty = unsafe { isatty(STDIN_FILENO) == 1 };
-        if is_a_tty {
-            eprintln!("Missing filename");
-            exit(1)
+        Theme::plain()
+    };
+
+    if let Some(command) = args.command {
+        let mut stdout = WriteDestination::Stdout(io::stdout().lock());
+        match command {
+            Command::Stats { mut input, top } => {
+                let filter = input.filter(Preset::default());
+                let mut stats = Stats::default();
+                for record in records(input.open(&filter, false), &filter, None) {
+                    stats.push(&record?.1);
+                }
+                write_line(&mut stdout, &stats.report(top, &theme), colour)?;
+            }
+            Command::Histogram {
+                mut input,
+                bucket,
+                width,
+            } => {
+                let filter = input.filter(Preset::default());
+                let mut histogram = Histogram::new(bucket);
+                for record in records(input.open(&filter, false), &filter, None) {
+                    histogram.push(&record?.1);
+                }
+                let width = width.unwrap_or_else(|| match to_terminal {
+                    true => crossterm::terminal::size().map_or(80, |(width, _)| width as usize),
+                    false => 80,
+                });
+                let lines = histogram
+                    .render(width, &theme, colour)
+                    .unwrap_or_else(|e| fail(e));
+                for line in lines {
+                    write_line(&mut stdout, &line, colour)?;
+                }
+            }
+            Command::Spans { mut input, top } => {
+                let filter = input.filter(Preset::default());
+                let mut spans = SpanTree::default();
+                for record in records(input.open(&filter, false), &filter, None) {
+                    spans.push(&record?.1);
+                }
+                for line in spans.report(top, &theme) {
+                    write_line(&mut stdout, &line, colour)?;
+                }
+            }
+            Command::Patterns { mut input, top } => {
+                let filter = input.filter(Preset::default());
+                let mut patterns = Patterns::default();
+                for record in records(input.open(&filter, false), &filter, None) {
+                    patterns.push(&record?.1);
+                }
+                for line in patterns.report(top, &theme) {
+                    write_line(&mut stdout, &line, colour)?;
+                }
+            }
         }
-        InputSource::Pipe(io::stdin().lock())
+        return Ok(());
+    }
+
+    let preset = match &args.preset {
+        Some(name) => config.preset(name).unwrap_or_else(|e| fail(e)).clone(),
+        None => Preset::default(),
     };
+    let grep_regex = args.grep.or_else(|| {
+        preset
+            .grep
+            .as_ref()
+            .map(|pattern| Regex::new(pattern).unwrap_or_else(|e| fail(e)))
+    });
+    let filter = args.input.filter(preset);
+    let sources = args.input.open(&filter, args.follow);
 
-    let mut child: Option<Child> = None;
-    let mut write_destination = if args.pipe {
-        WriteDestination::Stdout(io::stdout().lock())
+    theme.target_colours = args
+        .target_colors
+        .or(config.target_colors)
+        .unwrap_or_default();
+    let zone = args.tz.or_else(|| {
+        config
+            .tz
+            .as_deref()
+            .map(|tz| time::parse_zone(tz).unwrap_or_else(|e| fail(e)))
+    });
+    let time_format = args.time_format.or_else(|| {
+        config
+            .time_format
+            .as_deref()
+            .map(|format| time::parse_time_format(format).unwrap_or_else(|e| fail(e)))
+    });
+    let mut timestamps = TimestampDisplay::new(
+        args.timestamps.or(config.timestamps).unwrap_or_default(),
+        zone,
+        time_format,
+    );
+    let tags = if args.input.files.len() > 1 {
+        file_tags(&args.input.files, &theme)
     } else {
-        let mut less_process = Command::new("less")
-            .arg("-R")
-            .args(&args.less_args)
-            .stdin(Stdio::piped())
-            .spawn()
-            .expect("Failed to start less");
-
-        let less_stdin = less_process
-            .stdin
-            .take()
-            .expect("Failed to open less stdin");
-
-        child = Some(less_process);
-        WriteDestination::Less(less_stdin)
+        Vec::new()
     };
 
-    let mut general_format = None;
-    for line in reader.lines() {
-        let l = line?;
-        let new_line = if let Some(format) = general_format {
-            if let Some(full_format) = parse_line_path_from_general_format(&l, format) {
-                colorize_line(&l, full_format)
-            } else {
-                format!("FAILED TO PARSE LINE: {}", l)
-            }
-        } else if let Some(full_format) = parse_line_from_scratch(&l) {
-            general_format = Some(GeneralLineFormat {
-                tz_start: full_format.tz_start,
-                tz_end: full_format.tz_end,
-                level_start: full_format.level_start,
-                level_end: full_format.level_end,
-                path_start: full_format.path_start,
+    if args.tui {
+        if !to_terminal {
+            eprintln!("The viewer requires a terminal");
+            exit(1)
+        }
+        if grep_regex.is_some() {
+            eprintln!("The viewer cannot be used with a preset which greps");
+            exit(1)
+        }
+        let mut entries = Vec::new();
+        for record in records(sources, &filter, args.dedupe) {
+            let (source, record) = record?;
+            entries.push(tui::Entry {
+                lines: render_record(&record, tags.get(source), None, &theme, &mut timestamps)
+                    .split('\n')
+                    .map(str::to_string)
+                    .collect(),
+                log_type: record.format.map(|format| format.log_type),
+                target: record
+                    .format
+                    .map(|format| format.target(&record.line).to_string())
+                    .unwrap_or_default(),
             });
-            colorize_line(&l, full_format)
-        } else {
-            format!("FAILED TO PARSE LINE: {}", l)
+        }
+        return tui::run(entries, theme);
+    }
+
+    let (mut write_destination, child) = if args.pipe || !to_terminal {
+        (WriteDestination::Stdout(io::stdout().lock()), None)
+    } else {
+        let pager_args = match (args.pager_args.is_empty(), config.pager_args) {
+            (true, Some(pager_args)) => pager_args,
+            _ => args.pager_args,
         };
-        writeln!(write_destination, "{}", new_line)?;
+        output::open_pager(&pager_args)
+    };
+
+    let mut grep = grep_regex.map(|regex| {
+        Grep::new(
+            regex,
+            args.before_context.unwrap_or(args.context),
+            args.after_context.unwrap_or(args.context),
+        )
+    });
+
+    for record in records(sources, &filter, args.dedupe) {
+        let (source, record) = record?;
+        let Some(grep) = &mut grep else {
+            let new_line = render_record(&record, tags.get(source), None, &theme, &mut timestamps);
+            write_line(&mut write_destination, &new_line, colour)?;
+            continue;
+        };
+        let is_match = grep.is_match(&record);
+        for output in grep.push((source, record), is_match) {
+            let new_line = match output {
+                GrepOutput::Separator => format!("{}--{}", theme.separator, theme.reset),
+                GrepOutput::Match((source, record)) => render_record(
+                    &record,
+                    tags.get(source),
+                    Some(grep.regex()),
+                    &theme,
+                    &mut timestamps,
+                ),
+                GrepOutput::Context((source, record)) => {
+                    render_record(&record, tags.get(source), None, &theme, &mut timestamps)
+                }
+            };
+            write_line(&mut write_destination, &new_line, colour)?;
+        }
     }
 
     if let Some(mut child) = child {
@@ -103,189 +559,248 @@ fn main() -> io::Result<()> {
     Ok(())
 }
 
-enum InputSource {
-    File(io::BufReader<fs::File>),
-    Pipe(io::StdinLock<'static>),
+/// The records of the sources in timestamp order, along with the index of their
+/// source, leaving out those rejected by the filter and folding repeats if asked.
+/// The signal that a followed file is waiting has been acted on by this point, so
+/// it is left out too.
+fn records(
+    sources: Vec<Records<Lines>>,
+    filter: &Filter,
+    dedupe: Option<DedupeMode>,
+) -> Box<dyn Iterator<Item = io::Result<(usize, Record)>> + '_> {
+    let records = Merge::new(sources)
+        // Times of day are on the date of the first record, whether or not it is shown
+        .inspect(|record| {
+            if let Ok((_, record)) = record
+                && !filter.time_range.is_resolved()
+                && let Some(timestamp) = record.timestamp()
+            {
+                filter.time_range.bounds(timestamp);
+            }
+        })
+        .filter(move |record| match record {
+            Ok((_, record)) => record
+                .format
+                .is_none_or(|format| filter.matches(&record.line, &format)),
+            Err(_) => true,
+        });
+    let records: Box<dyn Iterator<Item = io::Result<(usize, Record)>>> = match dedupe {
+        Some(mode) => Box::new(Dedupe::new(records, mode)),
+        None => Box::new(records),
+    };
+    Box::new(
+        records.filter(|record| !matches!(record, Err(e) if e.kind() == io::ErrorKind::WouldBlock)),
+    )
 }
 
-impl std::io::Read for InputSource {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        match self {
-            Self::File(f) => f.read(buf),
-            Self::Pipe(p) => p.read(buf),
-        }
-    }
+/// Report an error in the arguments or config and exit
+fn fail(message: impl Display) -> ! {
+    eprintln!("{message}");
+    exit(1)
 }
 
-impl std::io::BufRead for InputSource {
-    fn consume(&mut self, amount: usize) {
-        match self {
-            Self::File(f) => f.consume(amount),
-            Self::Pipe(p) => p.consume(amount),
-        }
-    }
-    fn fill_buf(&mut self) -> io::Result<&[u8]> {
-        match self {
-            Self::File(f) => f.fill_buf(),
-            Self::Pipe(p) => p.fill_buf(),
-        }
-    }
+/// Report an input which could not be opened or read and exit
+fn fail_input(path: impl AsRef<Path>, e: io::Error) -> ! {
+    fail(format!("{}: {e}", path.as_ref().display()))
 }
 
-enum WriteDestination {
-    Stdout(io::StdoutLock<'static>),
-    Less(std::process::ChildStdin),
+/// Write a line, removing the colours if they are not wanted. If the reader has gone
+/// away, such as the pager being quit or a pipe into head, we are done.
+fn write_line(
+    write_destination: &mut WriteDestination,
+    line: &str,
+    colour: bool,
+) -> io::Result<()> {
+    let written = if colour {
+        writeln!(write_destination, "{}", line)
+    } else {
+        writeln!(write_destination, "{}", ansi::strip(line))
+    };
+    match written {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => exit(0),
+        written => written,
+    }
 }
 
-impl std::io::Write for WriteDestination {
-    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        match self {
-            Self::Less(w) => w.write(buf),
-            Self::Stdout(w) => w.write(buf),
-        }
+/// Colour a record for writing, highlighting any matches of `highlight` and
+/// prefixing each line with the tag of its file
+fn render_record(
+    record: &Record,
+    tag: Option<&String>,
+    highlight: Option<&Regex>,
+    theme: &Theme,
+    timestamps: &mut TimestampDisplay,
+) -> String {
+    let mut new_line = if let Some(full_format) = record.format {
+        colorize_record(record, full_format, theme, timestamps)
+    } else {
+        format!("FAILED TO PARSE LINE: {}", record.line)
+    };
+    if let Some(regex) = highlight {
+        let (matches, searched_len) = grep::find_matches(regex, record);
+        let start = ansi::strip(&new_line).len().saturating_sub(searched_len);
+        let matches = matches
+            .into_iter()
+            .map(|m| m.start + start..m.end + start)
+            .collect::<Vec<_>>();
+        new_line = ansi::highlight(&new_line, &matches, &theme.highlight);
     }
-    fn flush(&mut self) -> io::Result<()> {
-        match self {
-            Self::Less(w) => w.flush(),
-            Self::Stdout(w) => w.flush(),
-        }
+    if let Some(repeats) = record.repeats {
+        let suffix = repeats_suffix(record, repeats, theme);
+        new_line = match new_line.split_once('\n') {
+            Some((line, continuation)) => format!("{line}{suffix}\n{continuation}"),
+            None => format!("{new_line}{suffix}"),
+        };
     }
+    if let Some(tag) = tag {
+        new_line = format!("{tag}{}", new_line.replace('\n', &format!("\n{tag}")));
+    }
+    new_line
 }
 
-#[derive(Clone, Copy, Debug)]
-enum LogType {
-    Error,
-    Warn,
-    Info,
-    Debug,
-    Trace,
-}
-
-impl LogType {
-    pub fn as_colour_str(&self) -> &'static str {
-        match self {
-            Self::Error => "\x1b[91m", // Red
-            Self::Warn => "\x1b[93m",  // Yellow
-            Self::Info => "\x1b[92m",  // Green
-            Self::Debug => "\x1b[94m", // Blue
-            Self::Trace => "\x1b[95m", // Purple
+/// Describe the records folded into a record, eg. ` (×1432, 04:57:18–04:59:02)`
+fn repeats_suffix(record: &Record, repeats: Repeats, theme: &Theme) -> String {
+    let times = match (record.timestamp(), repeats.last) {
+        (Some(first), Some(last)) => {
+            format!(", {}–{}", first.format("%H:%M:%S"), last.format("%H:%M:%S"))
         }
-    }
+        _ => String::new(),
+    };
+    format!(
+        " {}(×{}{times}){}",
+        theme.timestamp, repeats.count, theme.reset
+    )
 }
 
-#[derive(Clone, Copy)]
-struct GeneralLineFormat {
-    tz_start: usize,
-    tz_end: usize,
-    level_start: usize,
-    level_end: usize,
-    path_start: usize,
-}
+/// Create a coloured tag from the name of each file, padded to the same width
+fn file_tags(files: &[String], theme: &Theme) -> Vec<String> {
+    const MAX_TAG_LEN: usize = 12;
 
-#[derive(Clone, Copy, Debug)]
-struct LineFormat {
-    log_type: LogType,
-    tz_start: usize,
-    tz_end: usize,
-    level_start: usize,
-    level_end: usize,
-    path_start: usize,
-    path_end: usize,
+    let names = files
+        .iter()
+        .map(|file| {
+            // Drop any compression extension before the log's own extension
+            let path = Path::new(file);
+            let path = match path.extension().and_then(|e| e.to_str()) {
+                Some("gz" | "zst" | "xz") => Path::new(path.file_stem().unwrap_or_default()),
+                _ => path,
+            };
+            let name = path.file_stem().unwrap_or(path.as_os_str());
+            name.to_string_lossy()
+                .chars()
+                .take(MAX_TAG_LEN)
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>();
+    let width = names
+        .iter()
+        .map(|name| name.chars().count())
+        .max()
+        .unwrap_or(0);
+    names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| format!("{}{name:<width$}{} ", theme.tag(i), theme.reset))
+        .collect()
 }
 
-/// Parse the line to obtain the full format.
-///
-/// Returns None if it fails to parse. Returning
-/// Some does not guarantee a correct parse.
-fn parse_line_from_scratch(line: &str) -> Option<LineFormat> {
-    if line.len() < 4 {
-        return None;
+/// Colour the record's line followed by its continuation lines, which are coloured as
+/// part of the message
+fn colorize_record(
+    record: &Record,
+    line_format: LineFormat,
+    theme: &Theme,
+    timestamps: &mut TimestampDisplay,
+) -> String {
+    let mut new_line = String::new();
+    if !record.line_shown {
+        new_line = colorize_line(&record.line, line_format, theme, timestamps);
     }
-    let mut space_indices = Vec::with_capacity(3);
-    let mut count = 0;
-    let mut prev_was_space = false;
-
-    for (i, c) in line.char_indices() {
-        if c == ' ' {
-            if !prev_was_space {
-                space_indices.push(i);
-                count += 1;
-                if count > 2 {
-                    break;
-                }
-            }
-
-            prev_was_space = true
-        } else {
-            prev_was_space = false
+    for (i, line) in record.continuation.iter().enumerate() {
+        if i > 0 || !record.line_shown {
+            new_line.push('\n');
         }
+        push_fields(&mut new_line, line, 0, line.len(), &theme.reset, theme);
     }
-
-    let log_type = match line[(space_indices[0] + 1)..(space_indices[1])]
-        .trim()
-        .to_ascii_lowercase()
-        .as_str()
-    {
-        "error" => LogType::Error,
-        "warn" => LogType::Warn,
-        "info" => LogType::Info,
-        "debug" => LogType::Debug,
-        "trace" => LogType::Trace,
-        _ => return None,
-    };
-
-    Some(LineFormat {
-        log_type,
-        tz_start: 0,
-        tz_end: space_indices[0] + 1,
-        level_start: space_indices[0] + 1,
-        level_end: space_indices[1] + 1,
-        path_start: space_indices[1] + 1,
-        path_end: space_indices[2] + 1,
-    })
+    new_line
 }
 
-/// Given a general format, parse the log type and path end location to create a full LineFormat
-fn parse_line_path_from_general_format(
+fn colorize_line(
     line: &str,
-    general_format: GeneralLineFormat,
-) -> Option<LineFormat> {
-    let log_type = match line[general_format.level_start..general_format.level_end]
-        .trim()
-        .to_ascii_lowercase()
-        .as_str()
-    {
-        "error" => LogType::Error,
-        "warn" => LogType::Warn,
-        "info" => LogType::Info,
-        "debug" => LogType::Debug,
-        "trace" => LogType::Trace,
-        _ => return None,
-    };
-
-    let path_end = line[general_format.path_start..].find(' ')? + general_format.path_start;
-    Some(LineFormat {
-        log_type,
-        tz_start: general_format.tz_start,
-        tz_end: general_format.tz_end,
-        level_start: general_format.level_start,
-        level_end: general_format.level_end,
-        path_start: general_format.path_start,
-        path_end,
-    })
-}
-
-fn colorize_line(line: &str, line_format: LineFormat) -> String {
+    line_format: LineFormat,
+    theme: &Theme,
+    timestamps: &mut TimestampDisplay,
+) -> String {
     let mut new_line = String::with_capacity(line.len() + 24);
-    let grey = "\x1b[90m";
-    new_line.push_str(grey);
-    new_line.push_str(&line[line_format.tz_start..line_format.tz_end]);
-    new_line.push_str(line_format.log_type.as_colour_str());
+    new_line.push_str(&timestamps.render(&line[line_format.tz_start..line_format.tz_end], theme));
+    new_line.push_str(theme.level(line_format.log_type));
     new_line.push_str(&line[line_format.level_start..line_format.level_end]);
-    new_line.push_str(grey);
+    push_spans(&mut new_line, line, &line_format, theme);
+    new_line.push_str(theme.target(line_format.target(line)));
     new_line.push_str(&line[line_format.path_start..line_format.path_end]);
-    new_line.push_str("\x1b[0m"); // Clear colour formatting for rest of string
-    new_line.push_str(&line[line_format.path_end..]);
+    new_line.push_str(&theme.reset); // Clear colour formatting for rest of string
+    push_fields(
+        &mut new_line,
+        line,
+        line_format.path_end,
+        line.len(),
+        &theme.reset,
+        theme,
+    );
 
     new_line
 }
+
+/// Colour the span context, with each span name followed by its fields
+fn push_spans(new_line: &mut String, line: &str, line_format: &LineFormat, theme: &Theme) {
+    if line_format.spans_start == line_format.spans_end {
+        return;
+    }
+    let mut written = line_format.spans_start;
+    for span in parse::parse_spans(line, line_format) {
+        new_line.push_str(&theme.punctuation);
+        new_line.push_str(&line[written..span.name_start]);
+        new_line.push_str(&theme.span_name);
+        new_line.push_str(&line[span.name_start..span.name_end]);
+        written = span.name_end;
+        if span.fields_start > span.name_end {
+            new_line.push_str(&theme.punctuation);
+            new_line.push_str(&line[written..span.fields_start]);
+            push_fields(
+                new_line,
+                line,
+                span.fields_start,
+                span.fields_end,
+                &theme.punctuation,
+                theme,
+            );
+            written = span.fields_end;
+        }
+    }
+    new_line.push_str(&theme.punctuation);
+    new_line.push_str(&line[written..line_format.spans_end]);
+}
+
+/// Write `line[start..end]` with the keys and values of any `key=value` fields
+/// styled, returning to the `base` style between fields
+fn push_fields(
+    new_line: &mut String,
+    line: &str,
+    start: usize,
+    end: usize,
+    base: &str,
+    theme: &Theme,
+) {
+    let mut written = start;
+    for field in parse::parse_fields(line, start, end) {
+        new_line.push_str(&line[written..field.key_start]);
+        new_line.push_str(&theme.field_key);
+        new_line.push_str(&line[field.key_start..field.key_end]);
+        new_line.push('=');
+        new_line.push_str(&theme.field_value);
+        new_line.push_str(&line[field.value_start..field.value_end]);
+        new_line.push_str(base);
+        written = field.value_end;
+    }
+    new_line.push_str(&line[written..end]);
+}
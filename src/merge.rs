@@ -0,0 +1,72 @@
+//! Interleaving of the records from several logs by timestamp.
+//!
+//! Each log is assumed to already be in time order, so we only need to compare the
+//! next record of each. Records without a timestamp, which failed to parse, are
+//! written as soon as they are reached.
+//!
+use std::io;
+
+use chrono::{DateTime, Utc};
+
+use crate::record::Record;
+
+/// Iterator over the records of several sources, returning the index of the source
+/// along with each record
+pub struct Merge<I> {
+    sources: Vec<I>,
+    heads: Vec<Option<(Option<DateTime<Utc>>, Record)>>,
+    started: bool,
+}
+
+impl<I: Iterator<Item = io::Result<Record>>> Merge<I> {
+    pub fn new(sources: Vec<I>) -> Self {
+        let heads = sources.iter().map(|_| None).collect();
+        Self {
+            sources,
+            heads,
+            started: false,
+        }
+    }
+
+    fn fill(&mut self, source: usize) -> io::Result<()> {
+        self.heads[source] = self.sources[source]
+            .next()
+            .transpose()?
+            .map(|record| (record.timestamp(), record));
+        Ok(())
+    }
+}
+
+impl<I: Iterator<Item = io::Result<Record>>> Iterator for Merge<I> {
+    type Item = io::Result<(usize, Record)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // A single source is passed straight through so that following can write
+        // records out as they arrive
+        if self.sources.len() == 1 {
+            return self.sources[0].next().map(|record| record.map(|r| (0, r)));
+        }
+        if !self.started {
+            self.started = true;
+            for source in 0..self.sources.len() {
+                if let Err(e) = self.fill(source) {
+                    return Some(Err(e));
+                }
+            }
+        }
+
+        // Ties go to the earlier source, and None sorts first
+        let next = self
+            .heads
+            .iter()
+            .enumerate()
+            .filter_map(|(i, head)| head.as_ref().map(|(timestamp, _)| (timestamp, i)))
+            .min()?
+            .1;
+        let (_, record) = self.heads[next].take()?;
+        if let Err(e) = self.fill(next) {
+            return Some(Err(e));
+        }
+        Some(Ok((next, record)))
+    }
+}
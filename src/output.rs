@@ -0,0 +1,88 @@
+//! Destinations for the coloured output.
+//!
+//! The pager is taken from `LOG_PAGER`, then `PAGER`, defaulting to less. Known pagers
+//! are given the flags they need to display the colours, and if the pager cannot be
+//! started we fall back to writing to stdout. Setting either variable to an empty
+//! string or `cat` disables the pager.
+//!
+use std::env;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
+
+pub enum WriteDestination {
+    Stdout(io::StdoutLock<'static>),
+    Pager(ChildStdin),
+}
+
+impl Write for WriteDestination {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Pager(w) => w.write(buf),
+            Self::Stdout(w) => w.write(buf),
+        }
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Pager(w) => w.flush(),
+            Self::Stdout(w) => w.flush(),
+        }
+    }
+}
+
+/// The flags a pager needs to pass the ANSI colours through to the terminal.
+/// most and moar display them by default.
+fn colour_args(program: &str) -> &'static [&'static str] {
+    match program {
+        "less" => &["-R"],
+        "bat" | "batcat" => &["--paging=always", "--plain"],
+        _ => &[],
+    }
+}
+
+/// The pager program and its arguments, or None if paging is disabled
+fn pager_command() -> Option<Vec<String>> {
+    let command = ["LOG_PAGER", "PAGER"]
+        .iter()
+        .find_map(|var| env::var(var).ok())
+        .unwrap_or_else(|| "less".to_string());
+    let command = command
+        .split_whitespace()
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    match command.first().map(String::as_str) {
+        None | Some("cat") => None,
+        Some(_) => Some(command),
+    }
+}
+
+/// Start the pager with the user's extra arguments, returning the destination to
+/// write to along with the pager process if it was started
+pub fn open_pager(extra_args: &[String]) -> (WriteDestination, Option<Child>) {
+    let stdout = || WriteDestination::Stdout(io::stdout().lock());
+    let Some(command) = pager_command() else {
+        return (stdout(), None);
+    };
+    let (program, args) = command.split_first().expect("pager command is not empty");
+    let name = Path::new(program)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(program);
+
+    let spawned = Command::new(program)
+        .args(colour_args(name))
+        .args(args)
+        .args(extra_args)
+        .stdin(Stdio::piped())
+        .spawn();
+    match spawned {
+        Ok(mut pager) => match pager.stdin.take() {
+            Some(pager_stdin) => (WriteDestination::Pager(pager_stdin), Some(pager)),
+            None => (stdout(), None),
+        },
+        Err(e) => {
+            eprintln!("Warning: failed to start pager {program:?} ({e}), writing to stdout");
+            (stdout(), None)
+        }
+    }
+}
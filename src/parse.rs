@@ -0,0 +1,607 @@
+//! Location of the components of a log line.
+//!
+//! Lines have the form `TIMESTAMP LOG_LEVEL SPANS: SOURCE: LOG_MESSAGE` where the
+//! span context is optional, eg.
+//!
+//! 2025-08-28T04:57:18.797136Z  INFO request{id=42}:db_query{table=users}: crate::db: message
+//!
+//! Each span in the context is a name optionally followed by its fields in braces,
+//! with the spans separated by `:`. The span fields may contain spaces, so the context
+//! cannot be split on whitespace and is instead scanned with brace matching.
+//!
+use clap::ValueEnum;
+use serde::Deserialize;
+
+/// Log levels, ordered from most to least severe
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogType {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogType {
+    /// Every level, in order, so that `log_type as usize` indexes it
+    pub const ALL: [LogType; 5] = [
+        Self::Error,
+        Self::Warn,
+        Self::Info,
+        Self::Debug,
+        Self::Trace,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Error => "ERROR",
+            Self::Warn => "WARN",
+            Self::Info => "INFO",
+            Self::Debug => "DEBUG",
+            Self::Trace => "TRACE",
+        }
+    }
+
+    /// Parse a level as written by tracing, ignoring case and padding
+    pub fn parse(level: &str) -> Option<Self> {
+        match level.trim().to_ascii_lowercase().as_str() {
+            "error" => Some(Self::Error),
+            "warn" => Some(Self::Warn),
+            "info" => Some(Self::Info),
+            "debug" => Some(Self::Debug),
+            "trace" => Some(Self::Trace),
+            _ => None,
+        }
+    }
+}
+
+/// The parts of the format which are constant within a set of logs
+#[derive(Clone, Copy)]
+pub struct GeneralLineFormat {
+    pub tz_start: usize,
+    pub tz_end: usize,
+    pub level_start: usize,
+}
+
+/// Byte offsets of each component of a line. The level, spans and path each
+/// include their trailing separator so that the slices are contiguous.
+#[derive(Clone, Copy, Debug)]
+pub struct LineFormat {
+    pub log_type: LogType,
+    pub tz_start: usize,
+    pub tz_end: usize,
+    pub level_start: usize,
+    pub level_end: usize,
+    /// Equal to spans_end if there is no span context
+    pub spans_start: usize,
+    pub spans_end: usize,
+    /// Equal to path_end if the target is not written
+    pub path_start: usize,
+    pub path_end: usize,
+}
+
+impl LineFormat {
+    /// The SOURCE of the line without its trailing `:`
+    pub fn target<'a>(&self, line: &'a str) -> &'a str {
+        line[self.path_start..self.path_end].trim_end_matches(':')
+    }
+}
+
+/// A single span within the span context of a line.
+///
+/// `fields_start..fields_end` excludes the braces and is empty if the span has no fields.
+#[derive(Clone, Copy, Debug)]
+pub struct SpanFormat {
+    pub name_start: usize,
+    pub name_end: usize,
+    pub fields_start: usize,
+    pub fields_end: usize,
+}
+
+/// Parses lines, reusing the general format found in the first line which parses
+#[derive(Default)]
+pub struct LineParser {
+    general_format: Option<GeneralLineFormat>,
+}
+
+impl LineParser {
+    pub fn parse(&mut self, line: &str) -> Option<LineFormat> {
+        if let Some(general_format) = self.general_format
+            && let Some(format) = parse_line_path_from_general_format(line, general_format)
+        {
+            return Some(format);
+        }
+        let format = parse_line_from_scratch(line)?;
+        self.general_format = Some(GeneralLineFormat {
+            tz_start: format.tz_start,
+            tz_end: format.tz_end,
+            level_start: format.level_start,
+        });
+        Some(format)
+    }
+}
+
+/// Parse the line to obtain the full format.
+///
+/// Returns None if it fails to parse. Returning
+/// Some does not guarantee a correct parse.
+pub fn parse_line_from_scratch(line: &str) -> Option<LineFormat> {
+    let tz_end = line.find(' ')? + 1;
+    parse_line_path_from_general_format(
+        line,
+        GeneralLineFormat {
+            tz_start: 0,
+            tz_end,
+            level_start: tz_end,
+        },
+    )
+}
+
+/// Given a general format, parse the log type, span context and path to create a full LineFormat
+pub fn parse_line_path_from_general_format(
+    line: &str,
+    general_format: GeneralLineFormat,
+) -> Option<LineFormat> {
+    // The level may be padded on either side
+    let rest = line.get(general_format.level_start..)?;
+    let level_word_start =
+        general_format.level_start + rest.len() - rest.trim_start_matches(' ').len();
+    let level_word_end = line[level_word_start..]
+        .find(' ')
+        .map_or(line.len(), |i| i + level_word_start);
+    let log_type = LogType::parse(&line[level_word_start..level_word_end])?;
+    let level_end = line.len() - line[level_word_end..].trim_start_matches(' ').len();
+
+    let (spans_end, path_start, path_end) = parse_context(line, level_end);
+    Some(LineFormat {
+        log_type,
+        tz_start: general_format.tz_start,
+        tz_end: general_format.tz_end,
+        level_start: general_format.level_start,
+        level_end,
+        spans_start: level_end,
+        spans_end,
+        path_start,
+        path_end,
+    })
+}
+
+/// Locate the span context and target following the level.
+///
+/// Returns `(spans_end, path_start, path_end)`, where the span context starts at `start`.
+fn parse_context(line: &str, start: usize) -> (usize, usize, usize) {
+    let Some(first_end) = context_chunk_end(line, start) else {
+        // Neither spans nor a target, only the message
+        return (start, start, start);
+    };
+    let first = &line[start..first_end - 1];
+    let second_end = context_chunk_end(line, first_end + 1);
+
+    let is_spans = match second_end {
+        _ if has_span_syntax(first) => true,
+        // Without fields, a single span looks like a crate root target. The target
+        // following it will usually be a module path, whereas a message will not.
+        Some(second_end) => {
+            let second = &line[first_end + 1..second_end - 1];
+            !first.contains("::") && second.contains("::") && !has_span_syntax(second)
+        }
+        None => false,
+    };
+
+    if !is_spans {
+        return (start, start, first_end);
+    }
+    match second_end {
+        Some(second_end) if !has_span_syntax(&line[first_end + 1..second_end - 1]) => {
+            (first_end + 1, first_end + 1, second_end)
+        }
+        // Spans written without a target
+        _ => (first_end, first_end, first_end),
+    }
+}
+
+/// Whether a context chunk contains braced fields or the `:` which separates spans
+fn has_span_syntax(chunk: &str) -> bool {
+    chunk.contains('{') || chunk.replace("::", "").contains(':')
+}
+
+/// Find the end of a `SPANS:` or `SOURCE:` chunk beginning at `start`.
+///
+/// Returns the index after the `:` which is followed by a space (or ends the line),
+/// ignoring anything inside braces. Returns None if whitespace is found outside of
+/// braces first, in which case we have reached the message.
+fn context_chunk_end(line: &str, start: usize) -> Option<usize> {
+    let bytes = line.as_bytes();
+    let mut depth = 0usize;
+    let mut in_quotes = false;
+    let mut escaped = false;
+    let mut i = start;
+    while i < bytes.len() {
+        let b = bytes[i];
+        if in_quotes {
+            match b {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_quotes = false,
+                _ => (),
+            }
+        } else {
+            match b {
+                b'{' => depth += 1,
+                b'}' => depth = depth.saturating_sub(1),
+                b'"' if depth > 0 => in_quotes = true,
+                b':' if depth == 0 && i > start && bytes.get(i + 1).is_none_or(|c| *c == b' ') => {
+                    return Some(i + 1);
+                }
+                b' ' if depth == 0 => return None,
+                _ => (),
+            }
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Split the span context of a line into its individual spans
+pub fn parse_spans(line: &str, line_format: &LineFormat) -> Vec<SpanFormat> {
+    // Exclude the trailing `: ` of the context
+    let context = line[line_format.spans_start..line_format.spans_end].trim_end();
+    let end = line_format.spans_start + context.len().saturating_sub(1);
+    parse_span_context(line, line_format.spans_start, end)
+}
+
+/// Split `line[start..end]`, a span context without its trailing `:`, into its spans
+pub fn parse_span_context(line: &str, start: usize, end: usize) -> Vec<SpanFormat> {
+    let bytes = line.as_bytes();
+    let mut spans = Vec::new();
+    let mut i = start;
+    while i < end {
+        let name_start = i;
+        while i < end && bytes[i] != b'{' && bytes[i] != b':' {
+            i += 1;
+        }
+        let name_end = i;
+        let (mut fields_start, mut fields_end) = (i, i);
+        if i < end && bytes[i] == b'{' {
+            fields_start = i + 1;
+            fields_end = matching_brace(line, i).unwrap_or(end).min(end);
+            i = (fields_end + 1).min(end);
+        }
+        spans.push(SpanFormat {
+            name_start,
+            name_end,
+            fields_start,
+            fields_end,
+        });
+        // Skip the `:` separating spans
+        i += 1;
+    }
+    spans
+}
+
+/// Given the index of an opening brace, find the index of its closing brace
+fn matching_brace(line: &str, open: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut in_quotes = false;
+    let mut escaped = false;
+    for (i, b) in line.bytes().enumerate().skip(open) {
+        if in_quotes {
+            match b {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_quotes = false,
+                _ => (),
+            }
+            continue;
+        }
+        match b {
+            b'"' => in_quotes = true,
+            b'{' => depth += 1,
+            b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+/// A `key=value` field, as offsets into the line
+#[derive(Clone, Copy, Debug)]
+pub struct FieldFormat {
+    pub key_start: usize,
+    pub key_end: usize,
+    pub value_start: usize,
+    pub value_end: usize,
+}
+
+/// Find the `key=value` fields within `line[start..end]`.
+///
+/// Values may be quoted strings or `Debug` formatted, eg. `Some([1, 2])` or
+/// `Config { a: 1 }`, in which case the brackets are matched to find the end.
+pub fn parse_fields(line: &str, start: usize, end: usize) -> Vec<FieldFormat> {
+    let bytes = line.as_bytes();
+    let mut fields = Vec::new();
+    let mut i = start;
+    while i < end {
+        if bytes[i] == b' ' {
+            i += 1;
+            continue;
+        }
+        let key_start = i;
+        while i < end && is_key_byte(bytes[i]) {
+            i += 1;
+        }
+        if i > key_start && i + 1 < end && bytes[i] == b'=' && bytes[i + 1] != b' ' {
+            let value_start = i + 1;
+            let value_end = field_value_end(bytes, value_start, end);
+            fields.push(FieldFormat {
+                key_start,
+                key_end: i,
+                value_start,
+                value_end,
+            });
+            i = value_end;
+        } else {
+            // Not a field, skip the rest of the word
+            while i < end && bytes[i] != b' ' {
+                i += 1;
+            }
+        }
+    }
+    fields
+}
+
+fn is_key_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || b == b'.'
+}
+
+/// Find the end of the field value beginning at `start`
+fn field_value_end(bytes: &[u8], start: usize, end: usize) -> usize {
+    let mut depth = 0usize;
+    let mut in_quotes = false;
+    let mut escaped = false;
+    let mut i = start;
+    while i < end {
+        let b = bytes[i];
+        if in_quotes {
+            match b {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => {
+                    in_quotes = false;
+                    if depth == 0 {
+                        return i + 1;
+                    }
+                }
+                _ => (),
+            }
+        } else {
+            match b {
+                b'"' => in_quotes = true,
+                b'{' | b'[' | b'(' => depth += 1,
+                b'}' | b']' | b')' => depth = depth.saturating_sub(1),
+                // Debug formatted structs are written as `Name { .. }`
+                b' ' if depth == 0 && bytes.get(i + 1) == Some(&b'{') => (),
+                b' ' if depth == 0 => return i,
+                _ => (),
+            }
+        }
+        i += 1;
+    }
+    end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMESTAMP: &str = "2025-08-28T04:57:18.797136Z";
+
+    /// The level, span context, target and message of a line, as found from scratch
+    fn parts(rest: &str) -> (LogType, String, String, String) {
+        let line = format!("{TIMESTAMP} {rest}");
+        let format = parse_line_from_scratch(&line).expect("the line parses");
+        assert_eq!(
+            &line[format.tz_start..format.tz_end],
+            format!("{TIMESTAMP} ")
+        );
+        (
+            format.log_type,
+            line[format.spans_start..format.spans_end].to_string(),
+            format.target(&line).to_string(),
+            line[format.path_end..].to_string(),
+        )
+    }
+
+    /// The name and fields of each span of a line
+    fn spans(rest: &str) -> Vec<(String, String)> {
+        let line = format!("{TIMESTAMP} {rest}");
+        let format = parse_line_from_scratch(&line).expect("the line parses");
+        parse_spans(&line, &format)
+            .iter()
+            .map(|span| {
+                (
+                    line[span.name_start..span.name_end].to_string(),
+                    line[span.fields_start..span.fields_end].to_string(),
+                )
+            })
+            .collect()
+    }
+
+    /// The key and value of each field of a message
+    fn fields(message: &str) -> Vec<(&str, &str)> {
+        parse_fields(message, 0, message.len())
+            .iter()
+            .map(|field| {
+                (
+                    &message[field.key_start..field.key_end],
+                    &message[field.value_start..field.value_end],
+                )
+            })
+            .collect()
+    }
+
+    fn owned(parts: (LogType, &str, &str, &str)) -> (LogType, String, String, String) {
+        (
+            parts.0,
+            parts.1.to_string(),
+            parts.2.to_string(),
+            parts.3.to_string(),
+        )
+    }
+
+    #[test]
+    fn target_without_spans() {
+        assert_eq!(
+            parts(" INFO my_crate::db: message"),
+            owned((LogType::Info, "", "my_crate::db", " message"))
+        );
+    }
+
+    #[test]
+    fn span_context() {
+        let line = "DEBUG request{id=42 method=GET}:db_query{table=users}: my_crate::db: message";
+        assert_eq!(
+            parts(line),
+            owned((
+                LogType::Debug,
+                "request{id=42 method=GET}:db_query{table=users}: ",
+                "my_crate::db",
+                " message"
+            ))
+        );
+        assert_eq!(
+            spans(line),
+            [
+                ("request".to_string(), "id=42 method=GET".to_string()),
+                ("db_query".to_string(), "table=users".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn span_without_fields() {
+        let line = " WARN db_query: my_crate::db: slow";
+        assert_eq!(
+            parts(line),
+            owned((LogType::Warn, "db_query: ", "my_crate::db", " slow"))
+        );
+        assert_eq!(spans(line), [("db_query".to_string(), String::new())]);
+    }
+
+    #[test]
+    fn crate_root_target() {
+        // A crate root looks like a span without fields, but no module path follows
+        assert_eq!(
+            parts("ERROR my_crate: failed to start"),
+            owned((LogType::Error, "", "my_crate", " failed to start"))
+        );
+    }
+
+    #[test]
+    fn message_containing_separator() {
+        assert_eq!(
+            parts(" INFO my_crate::net: error: timed out"),
+            owned((LogType::Info, "", "my_crate::net", " error: timed out"))
+        );
+        assert_eq!(
+            parts(" INFO my_crate: error: timed out"),
+            owned((LogType::Info, "", "my_crate", " error: timed out"))
+        );
+    }
+
+    #[test]
+    fn spans_without_target() {
+        assert_eq!(
+            parts(" INFO request{id=42}: message"),
+            owned((LogType::Info, "request{id=42}:", "", " message"))
+        );
+    }
+
+    #[test]
+    fn message_only() {
+        assert_eq!(
+            parts("TRACE just a message"),
+            owned((LogType::Trace, "", "", "just a message"))
+        );
+    }
+
+    #[test]
+    fn multibyte_text() {
+        let line = " INFO req{name=\"日本: {x}\"}: my_crate::ü: héllo wörld";
+        assert_eq!(
+            parts(line),
+            owned((
+                LogType::Info,
+                "req{name=\"日本: {x}\"}: ",
+                "my_crate::ü",
+                " héllo wörld"
+            ))
+        );
+        assert_eq!(
+            spans(line),
+            [("req".to_string(), "name=\"日本: {x}\"".to_string())]
+        );
+    }
+
+    #[test]
+    fn not_a_log_line() {
+        assert!(parse_line_from_scratch("   at src/main.rs:12").is_none());
+        assert!(parse_line_from_scratch("no_spaces").is_none());
+    }
+
+    #[test]
+    fn general_format_is_reused() {
+        let mut parser = LineParser::default();
+        let first = parser
+            .parse(&format!("{TIMESTAMP}  INFO my_crate: first"))
+            .expect("the line parses");
+        let line = format!("{TIMESTAMP} ERROR my_crate: second");
+        let second = parser.parse(&line).expect("the line parses");
+        assert_eq!(first.level_start, second.level_start);
+        assert_eq!(second.log_type, LogType::Error);
+        assert_eq!(&line[second.path_end..], " second");
+    }
+
+    #[test]
+    fn simple_fields() {
+        assert_eq!(
+            fields("done user_id=7 elapsed=12ms"),
+            [("user_id", "7"), ("elapsed", "12ms")]
+        );
+        assert_eq!(fields("a = b x= y"), []);
+    }
+
+    #[test]
+    fn quoted_fields() {
+        assert_eq!(
+            fields(r#"msg="a \"quoted\" b=c" n=1"#),
+            [("msg", r#""a \"quoted\" b=c""#), ("n", "1")]
+        );
+        assert_eq!(
+            fields("name=\"héllo wörld\" k=ü"),
+            [("name", "\"héllo wörld\""), ("k", "ü")]
+        );
+    }
+
+    #[test]
+    fn debug_fields() {
+        assert_eq!(
+            fields("cfg=Config { a: 1, b: [1, 2] } opt=Some([1, 2]) next=2"),
+            [
+                ("cfg", "Config { a: 1, b: [1, 2] }"),
+                ("opt", "Some([1, 2])"),
+                ("next", "2")
+            ]
+        );
+        assert_eq!(
+            fields("s=S { text: \"}\" } t=1"),
+            [("s", "S { text: \"}\" }"), ("t", "1")]
+        );
+    }
+}
@@ -0,0 +1,191 @@
+//! Message templates, for `log patterns`.
+//!
+//! In the style of the Drain algorithm, the variable parts of each message are
+//! masked: field values, quoted strings, UUIDs, hex IDs and numbers. Messages are
+//! then grouped by level, target and number of words, and within a group a message
+//! joins the most similar template sharing at least half of its words. The words which
+//! differ are replaced by a wildcard, so the template generalises as it grows.
+//!
+//! 2025-08-28T04:57:18.801000Z  WARN my_crate::net: retry 3 of 5 addr="10.0.0.1:80"
+//!
+//! becomes the template `retry <*> of <*> addr=<*>`.
+//!
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use regex::Regex;
+
+use crate::parse::{self, LogType};
+use crate::record::Record;
+use crate::theme::Theme;
+use crate::time;
+
+const WILDCARD: &str = "<*>";
+
+/// The fraction of words a message must share with a template to join it
+const SIMILARITY: f64 = 0.5;
+
+struct Pattern {
+    log_type: LogType,
+    target: String,
+    words: Vec<String>,
+    count: usize,
+    first: Option<DateTime<Utc>>,
+    last: Option<DateTime<Utc>>,
+}
+
+pub struct Patterns {
+    /// Matches the variable parts of a message which are not field values
+    variable: Regex,
+    patterns: Vec<Pattern>,
+    /// The patterns a message may join, by its level, target and number of words
+    groups: HashMap<(usize, String, usize), Vec<usize>>,
+}
+
+impl Default for Patterns {
+    fn default() -> Self {
+        let variable = Regex::new(concat!(
+            r#""(?:[^"\\]|\\.)*""#,
+            r"|\b[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}\b",
+            r"|\b0x[0-9a-fA-F]+\b",
+            r"|\b[0-9a-fA-F]*[0-9][0-9a-fA-F]*\b",
+            r"|\d+(?:\.\d+)?",
+        ))
+        .expect("the pattern is valid");
+        Self {
+            variable,
+            patterns: Vec::new(),
+            groups: HashMap::new(),
+        }
+    }
+}
+
+impl Patterns {
+    pub fn push(&mut self, record: &Record) {
+        let Some(format) = record.format else {
+            return;
+        };
+        if record.line_shown {
+            return;
+        }
+        let line = &record.line;
+        let words = self.mask(line, format.path_end);
+        let key = (
+            format.log_type as usize,
+            format.target(line).to_string(),
+            words.len(),
+        );
+        let group = self.groups.entry(key).or_default();
+        let best = group
+            .iter()
+            .map(|i| (*i, similarity(&self.patterns[*i].words, &words)))
+            .filter(|(_, similarity)| *similarity >= SIMILARITY)
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(i, _)| i);
+        let timestamp = record.timestamp();
+        match best {
+            Some(i) => {
+                let pattern = &mut self.patterns[i];
+                for (template, word) in pattern.words.iter_mut().zip(&words) {
+                    if template != word {
+                        *template = WILDCARD.to_string();
+                    }
+                }
+                pattern.count += 1;
+                if let Some(timestamp) = timestamp {
+                    pattern.first = Some(
+                        pattern
+                            .first
+                            .map_or(timestamp, |first| first.min(timestamp)),
+                    );
+                    pattern.last = Some(pattern.last.map_or(timestamp, |last| last.max(timestamp)));
+                }
+            }
+            None => {
+                group.push(self.patterns.len());
+                self.patterns.push(Pattern {
+                    log_type: format.log_type,
+                    target: format.target(line).to_string(),
+                    words,
+                    count: 1,
+                    first: timestamp,
+                    last: timestamp,
+                });
+            }
+        }
+    }
+
+    /// The words of the message starting at `start`, with its variable parts masked
+    fn mask(&self, line: &str, start: usize) -> Vec<String> {
+        let mut masked = String::new();
+        let mut end = start;
+        for field in parse::parse_fields(line, start, line.len()) {
+            masked.push_str(&line[end..field.value_start]);
+            masked.push_str(WILDCARD);
+            end = field.value_end;
+        }
+        masked.push_str(&line[end..]);
+        self.variable
+            .replace_all(&masked, WILDCARD)
+            .split_whitespace()
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// List the templates, most common first, or only the `top` most common
+    pub fn report(&self, top: Option<usize>, theme: &Theme) -> Vec<String> {
+        if self.patterns.is_empty() {
+            return vec!["No records".to_string()];
+        }
+        let mut patterns = self.patterns.iter().collect::<Vec<_>>();
+        patterns.sort_by(|a, b| b.count.cmp(&a.count).then(a.first.cmp(&b.first)));
+        let total = patterns.iter().map(|pattern| pattern.count).sum::<usize>();
+        let count_width = patterns[0].count.to_string().len();
+
+        let mut lines = vec![
+            format!("{} templates in {total} records", patterns.len()),
+            String::new(),
+        ];
+        for pattern in patterns.iter().take(top.unwrap_or(usize::MAX)) {
+            let template = pattern.words.join(" ").replace(
+                WILDCARD,
+                &format!("{}{WILDCARD}{}", theme.field_value, theme.reset),
+            );
+            let target = match pattern.target.as_str() {
+                "" => String::new(),
+                target => format!("{}{target}{}: ", theme.target(target), theme.reset),
+            };
+            lines.push(format!(
+                "{:>count_width$}  {}{:<5}{}  {target}{template}",
+                pattern.count,
+                theme.level(pattern.log_type),
+                pattern.log_type.as_str(),
+                theme.reset,
+            ));
+            if let (Some(first), Some(last)) = (pattern.first, pattern.last) {
+                lines.push(format!(
+                    "{:count_width$}  {}first {}  last {}{}",
+                    "",
+                    theme.timestamp,
+                    time::format_time(first),
+                    time::format_time(last),
+                    theme.reset
+                ));
+            }
+        }
+        lines
+    }
+}
+
+/// The fraction of the words of a template matched by a message of the same length
+fn similarity(template: &[String], words: &[String]) -> f64 {
+    if words.is_empty() {
+        return 1.0;
+    }
+    let same = template
+        .iter()
+        .zip(words)
+        .filter(|(template, word)| *template == WILDCARD || template == word)
+        .count();
+    same as f64 / words.len() as f64
+}
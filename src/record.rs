@@ -0,0 +1,139 @@
+//! Grouping of lines into log records.
+//!
+//! A message containing newlines, such as a panic backtrace or `{:#?}` output, is
+//! written across several lines of which only the first parses. Any line which fails
+//! to parse is therefore treated as a continuation of the record before it.
+//!
+//! A record is only complete once the next record starts, so when following a file
+//! the input signals with a `WouldBlock` error that it is waiting, and the pending
+//! record is written out early. The error is then passed on so that later stages
+//! holding back records can do the same. Continuation lines arriving after that are
+//! returned as a record whose line has already been shown.
+//!
+use std::io;
+
+use chrono::{DateTime, Utc};
+
+use crate::json;
+use crate::parse::{LineFormat, LineParser};
+use crate::time;
+
+/// A log line along with any continuation lines which follow it
+pub struct Record {
+    pub line: String,
+    /// None if the line failed to parse and there was no record for it to continue
+    pub format: Option<LineFormat>,
+    pub continuation: Vec<String>,
+    /// The line was written before these continuation lines arrived
+    pub line_shown: bool,
+    /// The identical records which followed this one, if they were folded into it
+    pub repeats: Option<Repeats>,
+}
+
+/// The records folded into a record by `--dedupe`
+#[derive(Clone, Copy, Debug)]
+pub struct Repeats {
+    /// The number of records, including the first
+    pub count: usize,
+    pub last: Option<DateTime<Utc>>,
+}
+
+impl Record {
+    pub fn timestamp(&self) -> Option<DateTime<Utc>> {
+        let format = self.format?;
+        time::parse_timestamp(&self.line[format.tz_start..format.tz_end])
+    }
+}
+
+/// Iterator over the records formed from an iterator of lines
+pub struct Records<I> {
+    lines: I,
+    parser: LineParser,
+    pending: Option<Record>,
+    /// The last record, if it was written out early
+    flushed: Option<(String, LineFormat)>,
+    /// The input is waiting, which has not yet been passed on
+    idle: bool,
+}
+
+impl<I> Records<I> {
+    pub fn new(lines: I) -> Self {
+        Self {
+            lines,
+            parser: LineParser::default(),
+            pending: None,
+            flushed: None,
+            idle: false,
+        }
+    }
+}
+
+impl<I: Iterator<Item = io::Result<String>>> Iterator for Records<I> {
+    type Item = io::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.idle {
+            self.idle = false;
+            return Some(Err(io::ErrorKind::WouldBlock.into()));
+        }
+        loop {
+            let Some(line) = self.lines.next() else {
+                return self.pending.take().map(Ok);
+            };
+            let line = match line {
+                Ok(line) => line,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    if let Some(pending) = self.pending.take()
+                        && let Some(format) = pending.format
+                    {
+                        self.flushed = Some((pending.line.clone(), format));
+                        self.idle = true;
+                        return Some(Ok(pending));
+                    }
+                    return Some(Err(e));
+                }
+                Err(e) => return Some(Err(e)),
+            };
+            let (line, format) = match json::normalise_line(&line) {
+                Some((line, format)) => (line, Some(format)),
+                None => {
+                    let format = self.parser.parse(&line);
+                    (line, format)
+                }
+            };
+            if format.is_none()
+                && let Some(pending) = &mut self.pending
+            {
+                pending.continuation.push(line);
+                continue;
+            }
+            if format.is_none()
+                && let Some((flushed_line, flushed_format)) = &self.flushed
+            {
+                self.pending = Some(Record {
+                    line: flushed_line.clone(),
+                    format: Some(*flushed_format),
+                    continuation: vec![line],
+                    line_shown: true,
+                    repeats: None,
+                });
+                continue;
+            }
+
+            let record = Record {
+                line,
+                format,
+                continuation: Vec::new(),
+                line_shown: false,
+                repeats: None,
+            };
+            if format.is_none() {
+                return Some(Ok(record));
+            }
+            self.flushed = None;
+            if let Some(previous) = self.pending.replace(record) {
+                return Some(Ok(previous));
+            }
+        }
+    }
+}
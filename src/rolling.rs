@@ -0,0 +1,122 @@
+//! Discovery of the files written by `tracing_appender::rolling`.
+//!
+//! A rolling appender writes to `PREFIX.DATE` or `PREFIX.DATE.SUFFIX`, where DATE is
+//! `%Y-%m-%d`, `%Y-%m-%d-%H` or `%Y-%m-%d-%H-%M` in UTC for daily, hourly and minutely
+//! rotation respectively. The length of the date therefore tells us the period which
+//! the file covers, so files outside of a time window can be skipped without opening them.
+//!
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeDelta, Utc};
+
+use crate::input::{InputSource, Lines};
+
+pub struct RolledFile {
+    pub path: PathBuf,
+    /// The period covered by the file, or None if it is never rotated
+    pub period: Option<(DateTime<Utc>, DateTime<Utc>)>,
+}
+
+impl RolledFile {
+    /// Whether the file may contain records between `since` and `until`
+    pub fn overlaps(&self, since: Option<DateTime<Utc>>, until: Option<DateTime<Utc>>) -> bool {
+        let Some((start, end)) = self.period else {
+            return true;
+        };
+        since.is_none_or(|since| end > since) && until.is_none_or(|until| start <= until)
+    }
+}
+
+/// Find the files in `dir` written with `prefix`, sorted by date
+pub fn find_rolled_files(dir: &Path, prefix: &str) -> io::Result<Vec<RolledFile>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        let name = entry.file_name();
+        let Some(name) = name.to_str() else {
+            continue;
+        };
+
+        let rest = if prefix.is_empty() {
+            Some(name)
+        } else {
+            name.strip_prefix(prefix)
+                .and_then(|rest| rest.strip_prefix('.').or(rest.is_empty().then_some(rest)))
+        };
+        let Some(rest) = rest else {
+            continue;
+        };
+        if rest.is_empty() {
+            // Written with `Rotation::NEVER`
+            files.push(RolledFile {
+                path: entry.path(),
+                period: None,
+            });
+        } else if let Some(period) = parse_period(rest) {
+            files.push(RolledFile {
+                path: entry.path(),
+                period: Some(period),
+            });
+        }
+    }
+    // The never rotated file sorts last, as it is always the newest
+    files.sort_by_key(|file| (file.period.is_none(), file.period));
+    Ok(files)
+}
+
+/// Parse the date at the start of the rest of a file name into the period it covers
+fn parse_period(rest: &str) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let date_len = rest.find('.').unwrap_or(rest.len());
+    let date = &rest[..date_len];
+    let (start, length) = match date.len() {
+        10 => (
+            NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .ok()?
+                .and_hms_opt(0, 0, 0)?,
+            TimeDelta::days(1),
+        ),
+        13 => (
+            NaiveDateTime::parse_from_str(&format!("{date}-00"), "%Y-%m-%d-%H-%M").ok()?,
+            TimeDelta::hours(1),
+        ),
+        16 => (
+            NaiveDateTime::parse_from_str(date, "%Y-%m-%d-%H-%M").ok()?,
+            TimeDelta::minutes(1),
+        ),
+        _ => return None,
+    };
+    let start = start.and_utc();
+    Some((start, start + length))
+}
+
+/// Read the files one after another as a single log, starting the first file at `since`
+pub fn lines(
+    files: Vec<RolledFile>,
+    since: impl FnOnce(DateTime<Utc>) -> Option<DateTime<Utc>>,
+) -> io::Result<Lines> {
+    let mut files = files.into_iter();
+    let Some(first) = files.next() else {
+        return Ok(Box::new(std::iter::empty()));
+    };
+    let mut first = open(&first.path)?;
+    first.seek_to_time(since)?;
+
+    let rest = files.flat_map(|file| -> Lines {
+        match open(&file.path) {
+            Ok(source) => Box::new(source.lines()),
+            Err(e) => Box::new(std::iter::once(Err(e))),
+        }
+    });
+    Ok(Box::new(first.lines().chain(rest)))
+}
+
+/// Open a file, naming it in any error as the files are not named on the command line
+fn open(path: &Path) -> io::Result<InputSource> {
+    InputSource::open(path)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display())))
+}
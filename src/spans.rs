@@ -0,0 +1,334 @@
+//! Span trees and timings from span events, for `log spans`.
+//!
+//! With `FmtSpan` events enabled, tracing writes an event when a span is created,
+//! entered, exited and closed, eg.
+//!
+//! 2025-08-28T04:57:18.009700Z DEBUG request{id=42}:db_query{table=users}: my_crate::db: close time.busy=1.20ms time.idle=100µs
+//!
+//! The span context of an event ends with the span itself, so the context without its
+//! last span is the parent. Spans are matched to their parent by the context as it
+//! is written, which is ambiguous only for concurrent spans with identical fields.
+//! When only close events are written, a span closes after its children, so the
+//! children wait for their parent to close.
+//!
+use std::collections::HashMap;
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+use crate::parse::{self, LineFormat, LogType};
+use crate::record::Record;
+use crate::theme::Theme;
+
+struct Span {
+    /// The span as written in the context, eg. `db_query{table=users}`
+    span: String,
+    name: String,
+    /// The full span context, eg. `request{id=42}:db_query{table=users}`
+    context: String,
+    target: String,
+    log_type: LogType,
+    closed: Option<DateTime<Utc>>,
+    busy: Option<TimeDelta>,
+    idle: Option<TimeDelta>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+}
+
+/// The lifecycle of a span, taken from the message of its events
+enum SpanEvent {
+    New,
+    Enter,
+    Exit,
+    Close,
+}
+
+#[derive(Default)]
+pub struct SpanTree {
+    spans: Vec<Span>,
+    /// The spans which have not closed, by their context, most recent last
+    open: HashMap<String, Vec<usize>>,
+    /// Closed spans waiting for their parent to close, by the parent's context
+    orphans: HashMap<String, Vec<usize>>,
+}
+
+impl SpanTree {
+    pub fn push(&mut self, record: &Record) {
+        let Some(format) = record.format else {
+            return;
+        };
+        if record.line_shown {
+            return;
+        }
+        let line = &record.line;
+        let Some((context, target, message)) = span_event_parts(line, &format) else {
+            return;
+        };
+        let event = match message.split(' ').next() {
+            Some("new") if message == "new" => SpanEvent::New,
+            Some("enter") if message == "enter" => SpanEvent::Enter,
+            Some("exit") if message == "exit" => SpanEvent::Exit,
+            Some("close") => SpanEvent::Close,
+            _ => return,
+        };
+        let open = self.open.get(context).and_then(|open| open.last().copied());
+        match event {
+            SpanEvent::New => {
+                self.open_span(context, target, format.log_type);
+            }
+            SpanEvent::Enter | SpanEvent::Exit => {
+                if open.is_none() {
+                    self.open_span(context, target, format.log_type);
+                }
+            }
+            SpanEvent::Close => {
+                let i = match open {
+                    Some(i) => {
+                        self.open.get_mut(context).map(Vec::pop);
+                        i
+                    }
+                    None => self.add_span(context, target, format.log_type),
+                };
+                let start = line.len() - message.len();
+                let span = &mut self.spans[i];
+                span.closed = record.timestamp();
+                for field in parse::parse_fields(line, start, line.len()) {
+                    let value = &line[field.value_start..field.value_end];
+                    match &line[field.key_start..field.key_end] {
+                        "time.busy" => span.busy = parse_span_duration(value),
+                        "time.idle" => span.idle = parse_span_duration(value),
+                        _ => (),
+                    }
+                }
+                let parent_context = parent_context(context);
+                if span.parent.is_none() && !parent_context.is_empty() {
+                    self.orphans
+                        .entry(parent_context.to_string())
+                        .or_default()
+                        .push(i);
+                }
+                // Children which closed first, as they do when only close events are written
+                if let Some(children) = self.orphans.remove(context) {
+                    for child in children {
+                        self.spans[child].parent = Some(i);
+                        self.spans[i].children.push(child);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Create a span which has been opened, attaching it to its open parent
+    fn open_span(&mut self, context: &str, target: &str, log_type: LogType) {
+        let i = self.add_span(context, target, log_type);
+        self.open.entry(context.to_string()).or_default().push(i);
+    }
+
+    fn add_span(&mut self, context: &str, target: &str, log_type: LogType) -> usize {
+        let parent_context = parent_context(context);
+        let span = context[parent_context.len()..].trim_start_matches(':');
+        let i = self.spans.len();
+        let parent = self
+            .open
+            .get(parent_context)
+            .and_then(|open| open.last().copied());
+        if let Some(parent) = parent {
+            self.spans[parent].children.push(i);
+        }
+        self.spans.push(Span {
+            span: span.to_string(),
+            name: span.split('{').next().unwrap_or(span).to_string(),
+            context: context.to_string(),
+            target: target.to_string(),
+            log_type,
+            closed: None,
+            busy: None,
+            idle: None,
+            parent,
+            children: Vec::new(),
+        });
+        i
+    }
+
+    /// Draw each tree of spans, with the busy and idle time of each span, followed by
+    /// the `top` slowest spans of each name
+    pub fn report(&self, top: usize, theme: &Theme) -> Vec<String> {
+        if self.spans.is_empty() {
+            return vec!["No span events, enable them with FmtSpan".to_string()];
+        }
+        let mut lines = vec![format!(
+            "{}{:>10}  {:>10}  span{}",
+            theme.span_name, "busy", "idle", theme.reset
+        )];
+        for (i, _) in self
+            .spans
+            .iter()
+            .enumerate()
+            .filter(|(_, span)| span.parent.is_none())
+        {
+            self.push_tree(&mut lines, i, "", "", theme);
+        }
+
+        let mut by_name: HashMap<&str, Vec<&Span>> = HashMap::new();
+        for span in self.spans.iter().filter(|span| span.busy.is_some()) {
+            by_name.entry(&span.name).or_default().push(span);
+        }
+        let mut by_name = by_name.into_iter().collect::<Vec<_>>();
+        for (_, spans) in &mut by_name {
+            spans.sort_by_key(|span| std::cmp::Reverse(span.busy));
+        }
+        by_name.sort_by_key(|(name, spans)| (std::cmp::Reverse(spans[0].busy), *name));
+
+        lines.push(String::new());
+        lines.push(format!(
+            "{}Slowest spans by name{}",
+            theme.span_name, theme.reset
+        ));
+        for (name, spans) in by_name {
+            let total = spans.iter().filter_map(|span| span.busy).sum::<TimeDelta>();
+            let mean = total / spans.len() as i32;
+            lines.push(format!(
+                "{}{name}{}  count {}  busy total {}  mean {}",
+                theme.span_name,
+                theme.reset,
+                spans.len(),
+                format_duration(Some(total)),
+                format_duration(Some(mean)),
+            ));
+            for span in spans.iter().take(top) {
+                let closed = span
+                    .closed
+                    .map(|closed| format!("  closed {}", closed.format("%Y-%m-%dT%H:%M:%S%.6fZ")))
+                    .unwrap_or_default();
+                lines.push(format!(
+                    "{:>10}  {}{}{closed}{}",
+                    format_duration(span.busy),
+                    theme.punctuation,
+                    span.context,
+                    theme.reset
+                ));
+            }
+        }
+        lines
+    }
+
+    fn push_tree(
+        &self,
+        lines: &mut Vec<String>,
+        i: usize,
+        prefix: &str,
+        child_prefix: &str,
+        theme: &Theme,
+    ) {
+        let span = &self.spans[i];
+        let fields = &span.span[span.name.len()..];
+        lines.push(format!(
+            "{:>10}  {}{:>10}{}  {}{prefix}{}{}{}{fields} {}{}{}",
+            format_duration(span.busy),
+            theme.timestamp,
+            format_duration(span.idle),
+            theme.reset,
+            theme.punctuation,
+            theme.level(span.log_type),
+            span.name,
+            theme.punctuation,
+            theme.target(&span.target),
+            span.target,
+            theme.reset,
+        ));
+        for (n, child) in span.children.iter().enumerate() {
+            let (branch, continuation) = if n + 1 == span.children.len() {
+                ("└─ ", "   ")
+            } else {
+                ("├─ ", "│  ")
+            };
+            self.push_tree(
+                lines,
+                *child,
+                &format!("{child_prefix}{branch}"),
+                &format!("{child_prefix}{continuation}"),
+                theme,
+            );
+        }
+    }
+}
+
+/// The span context, target and message of a span event. A span without fields
+/// followed by a crate root target reads like a target followed by the message, so
+/// in that case the target is taken from the message.
+fn span_event_parts<'a>(line: &'a str, format: &LineFormat) -> Option<(&'a str, &'a str, &'a str)> {
+    let message = line[format.path_end..].trim_start();
+    if format.spans_start != format.spans_end {
+        let context = line[format.spans_start..format.spans_end].trim_end();
+        let context = context.strip_suffix(':').unwrap_or(context);
+        return Some((context, format.target(line), message));
+    }
+    let (target, message) = message.split_once(": ")?;
+    let context = format.target(line);
+    (!context.is_empty() && !target.contains(' ')).then_some((context, target, message))
+}
+
+/// The context of the parent of the last span in `context`, which is empty for a root
+fn parent_context(context: &str) -> &str {
+    let spans = parse::parse_span_context(context, 0, context.len());
+    match spans.last() {
+        // Up to the `:` before the last span
+        Some(last) if spans.len() > 1 => &context[..last.name_start - 1],
+        _ => "",
+    }
+}
+
+/// Parse a duration as written by tracing, eg. `1.20ms`, `300µs` or `1.5s`
+fn parse_span_duration(s: &str) -> Option<TimeDelta> {
+    let unit_start = s.find(|c: char| !c.is_ascii_digit() && c != '.')?;
+    let value: f64 = s[..unit_start].parse().ok()?;
+    let nanos = match &s[unit_start..] {
+        "ns" => 1.0,
+        "µs" | "us" => 1e3,
+        "ms" => 1e6,
+        "s" => 1e9,
+        _ => return None,
+    };
+    Some(TimeDelta::nanoseconds((value * nanos).round() as i64))
+}
+
+/// Format a duration to three significant figures, as tracing does
+fn format_duration(duration: Option<TimeDelta>) -> String {
+    let Some(nanos) = duration.and_then(|duration| duration.num_nanoseconds()) else {
+        return "-".to_string();
+    };
+    let nanos = nanos as f64;
+    let (value, unit) = match nanos {
+        n if n < 1e3 => (n, "ns"),
+        n if n < 1e6 => (n / 1e3, "µs"),
+        n if n < 1e9 => (n / 1e6, "ms"),
+        n => (n / 1e9, "s"),
+    };
+    let precision = match value {
+        v if v < 10.0 => 2,
+        v if v < 100.0 => 1,
+        _ => 0,
+    };
+    format!("{value:.precision$}{unit}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parent_contexts() {
+        assert_eq!(parent_context("request"), "");
+        assert_eq!(parent_context("request{id=42}"), "");
+        assert_eq!(
+            parent_context("request{id=42}:db_query{table=users}"),
+            "request{id=42}"
+        );
+        assert_eq!(parent_context("a:b:c"), "a:b");
+        // Separators and braces within quoted fields, including escaped quotes
+        assert_eq!(
+            parent_context(r#"request{path="a:\"}b"}:db_query"#),
+            r#"request{path="a:\"}b"}"#
+        );
+        assert_eq!(parent_context(r#"request{path="a:\"}b:c"}"#), "");
+    }
+}
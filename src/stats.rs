@@ -0,0 +1,140 @@
+//! A summary of a log, for `log stats`.
+//!
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+use crate::parse::LogType;
+use crate::record::Record;
+use crate::theme::Theme;
+use crate::time;
+
+/// Counts of the records seen
+#[derive(Default)]
+pub struct Stats {
+    counts: [usize; 5],
+    /// The records and errors written by each target
+    targets: HashMap<String, (usize, usize)>,
+    first: Option<DateTime<Utc>>,
+    last: Option<DateTime<Utc>>,
+    previous: Option<DateTime<Utc>>,
+    /// The longest time between consecutive records, and the time it ended
+    longest_gap: Option<(TimeDelta, DateTime<Utc>)>,
+    continuation_lines: usize,
+    /// Lines which did not parse and had no record to continue
+    failed_lines: usize,
+}
+
+impl Stats {
+    pub fn push(&mut self, record: &Record) {
+        let Some(format) = record.format else {
+            self.failed_lines += 1 + record.continuation.len();
+            return;
+        };
+        self.continuation_lines += record.continuation.len();
+        if record.line_shown {
+            return;
+        }
+        self.counts[format.log_type as usize] += 1;
+        let target = self
+            .targets
+            .entry(format.target(&record.line).to_string())
+            .or_default();
+        target.0 += 1;
+        if format.log_type == LogType::Error {
+            target.1 += 1;
+        }
+
+        let Some(timestamp) = record.timestamp() else {
+            return;
+        };
+        self.first = Some(self.first.map_or(timestamp, |first| first.min(timestamp)));
+        self.last = Some(self.last.map_or(timestamp, |last| last.max(timestamp)));
+        if let Some(previous) = self.previous.replace(timestamp) {
+            let gap = timestamp - previous;
+            if self.longest_gap.is_none_or(|(longest, _)| gap > longest) {
+                self.longest_gap = Some((gap, timestamp));
+            }
+        }
+    }
+
+    pub fn report(&self, top: usize, theme: &Theme) -> String {
+        let total = self.counts.iter().sum::<usize>();
+        let mut report = String::new();
+        let _ = writeln!(report, "Records             {total}");
+        for (log_type, count) in LogType::ALL.iter().zip(self.counts) {
+            let percent = match total {
+                0 => 0.0,
+                total => count as f64 * 100.0 / total as f64,
+            };
+            let _ = writeln!(
+                report,
+                "  {}{:<5}{}             {count:<10} {percent:5.1}%",
+                theme.level(*log_type),
+                log_type.as_str(),
+                theme.reset
+            );
+        }
+        let _ = writeln!(report, "Continuation lines  {}", self.continuation_lines);
+        let _ = writeln!(report, "Failed to parse     {}", self.failed_lines);
+
+        if let (Some(first), Some(last)) = (self.first, self.last) {
+            let duration = last - first;
+            let _ = writeln!(report);
+            let _ = writeln!(report, "First               {}", time::format_time(first));
+            let _ = writeln!(report, "Last                {}", time::format_time(last));
+            let _ = writeln!(
+                report,
+                "Duration            {}",
+                time::format_delta(duration)
+            );
+            if let Some(micros) = duration.num_microseconds().filter(|micros| *micros > 0) {
+                let rate = total as f64 / (micros as f64 / 1e6);
+                let _ = writeln!(report, "Rate                {rate:.2} records/s");
+            }
+            if let Some((gap, end)) = self.longest_gap {
+                let _ = writeln!(
+                    report,
+                    "Longest gap         {} before {}",
+                    time::format_delta(gap),
+                    time::format_time(end)
+                );
+            }
+        }
+
+        let mut by_volume = self.targets.iter().collect::<Vec<_>>();
+        by_volume.sort_by(|a, b| b.1.0.cmp(&a.1.0).then(a.0.cmp(b.0)));
+        let _ = writeln!(report);
+        let _ = writeln!(report, "Top targets by volume");
+        for (target, (count, _)) in by_volume.iter().take(top) {
+            let _ = writeln!(report, "  {count:>10}  {}", display_target(target));
+        }
+
+        let mut by_errors = self
+            .targets
+            .iter()
+            .filter(|(_, (_, errors))| *errors > 0)
+            .collect::<Vec<_>>();
+        by_errors.sort_by(|a, b| b.1.1.cmp(&a.1.1).then(a.0.cmp(b.0)));
+        if !by_errors.is_empty() {
+            let _ = writeln!(report);
+            let _ = writeln!(report, "Top targets by errors");
+            for (target, (_, errors)) in by_errors.iter().take(top) {
+                let _ = writeln!(
+                    report,
+                    "  {}{errors:>10}{}  {}",
+                    theme.level(LogType::Error),
+                    theme.reset,
+                    display_target(target)
+                );
+            }
+        }
+        report.trim_end().to_string()
+    }
+}
+
+/// Records without a target are grouped under an empty name
+fn display_target(target: &str) -> &str {
+    if target.is_empty() { "(none)" } else { target }
+}
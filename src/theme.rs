@@ -0,0 +1,554 @@
+//! Colour themes.
+//!
+//! A theme gives the style of each component of a line. A style is written as a space
+//! separated list of attributes and colours, eg. `bold #ff8700 on 236`, where a colour
+//! is one of the 16 named terminal colours, a 256-colour index or a 24-bit RGB hex
+//! value, and `on` introduces the background. Colours are reduced to what the terminal
+//! supports, as advertised by `COLORTERM` and `TERM`.
+//!
+use std::env;
+use std::str::FromStr;
+
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::parse::LogType;
+
+const NAMED_COLOURS: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+/// The RGB values of the 16 named colours, as used by xterm
+const NAMED_RGB: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+/// The levels of each channel in the 6x6x6 colour cube of the 256-colour palette
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Colour {
+    /// One of the 16 terminal colours, where 8 to 15 are the bright variants
+    Named(u8),
+    /// An index into the 256-colour palette
+    Fixed(u8),
+    Rgb(u8, u8, u8),
+}
+
+impl Colour {
+    fn rgb(self) -> (u8, u8, u8) {
+        match self {
+            Self::Named(n) => NAMED_RGB[n as usize % 16],
+            Self::Fixed(n) if n < 16 => NAMED_RGB[n as usize],
+            Self::Fixed(n) if n >= 232 => {
+                let level = 8 + (n - 232) * 10;
+                (level, level, level)
+            }
+            Self::Fixed(n) => {
+                let n = n - 16;
+                (
+                    CUBE_LEVELS[n as usize / 36],
+                    CUBE_LEVELS[n as usize / 6 % 6],
+                    CUBE_LEVELS[n as usize % 6],
+                )
+            }
+            Self::Rgb(r, g, b) => (r, g, b),
+        }
+    }
+
+    /// The nearest colour which can be shown at the given depth
+    fn reduce(self, depth: ColourDepth) -> Self {
+        match (self, depth) {
+            (Self::Rgb(..), ColourDepth::Fixed) => Self::Fixed(nearest_fixed(self.rgb())),
+            (Self::Rgb(..) | Self::Fixed(_), ColourDepth::Basic) => {
+                Self::Named(nearest_named(self.rgb()))
+            }
+            _ => self,
+        }
+    }
+
+    /// The SGR parameters selecting this as the foreground or background colour
+    fn sgr(self, background: bool) -> String {
+        let (base, bright_base, extended) = if background {
+            (40, 100, 48)
+        } else {
+            (30, 90, 38)
+        };
+        match self {
+            Self::Named(n) if n < 8 => (base + n).to_string(),
+            Self::Named(n) => (bright_base + n % 8).to_string(),
+            Self::Fixed(n) => format!("{extended};5;{n}"),
+            Self::Rgb(r, g, b) => format!("{extended};2;{r};{g};{b}"),
+        }
+    }
+}
+
+impl FromStr for Colour {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_ascii_lowercase();
+        if let Some(hex) = s.strip_prefix('#')
+            && hex.len() == 6
+            && let Ok(rgb) = u32::from_str_radix(hex, 16)
+        {
+            return Ok(Self::Rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8));
+        }
+        if let Ok(n) = s.parse() {
+            return Ok(Self::Fixed(n));
+        }
+        if s == "grey" || s == "gray" {
+            return Ok(Self::Named(8));
+        }
+        let (name, bright) = match s.strip_prefix("bright-") {
+            Some(name) => (name, 8),
+            None => (s.as_str(), 0),
+        };
+        NAMED_COLOURS
+            .iter()
+            .position(|named| *named == name)
+            .map(|n| Self::Named(n as u8 + bright))
+            .ok_or_else(|| format!("unknown colour {s:?}"))
+    }
+}
+
+fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> i32 {
+    let (dr, dg, db) = (
+        r1 as i32 - r2 as i32,
+        g1 as i32 - g2 as i32,
+        b1 as i32 - b2 as i32,
+    );
+    dr * dr + dg * dg + db * db
+}
+
+fn nearest_fixed(rgb: (u8, u8, u8)) -> u8 {
+    (16..=255)
+        .min_by_key(|n| distance(rgb, Colour::Fixed(*n).rgb()))
+        .unwrap_or(16)
+}
+
+fn nearest_named(rgb: (u8, u8, u8)) -> u8 {
+    (0..16)
+        .min_by_key(|n| distance(rgb, NAMED_RGB[*n as usize]))
+        .unwrap_or(7)
+}
+
+/// The colours a terminal can display
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColourDepth {
+    Basic,
+    Fixed,
+    TrueColour,
+}
+
+impl ColourDepth {
+    pub fn detect() -> Self {
+        let colour_term = env::var("COLORTERM").unwrap_or_default();
+        if colour_term == "truecolor" || colour_term == "24bit" {
+            Self::TrueColour
+        } else if env::var("TERM").is_ok_and(|term| term.contains("256")) {
+            Self::Fixed
+        } else {
+            Self::Basic
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Style {
+    pub fg: Option<Colour>,
+    pub bg: Option<Colour>,
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub reverse: bool,
+}
+
+impl Style {
+    const fn fg(colour: Colour) -> Self {
+        Self {
+            fg: Some(colour),
+            bg: None,
+            bold: false,
+            dim: false,
+            italic: false,
+            underline: false,
+            reverse: false,
+        }
+    }
+
+    const fn bold(mut self) -> Self {
+        self.bold = true;
+        self
+    }
+
+    const fn on(mut self, colour: Colour) -> Self {
+        self.bg = Some(colour);
+        self
+    }
+
+    /// The escape sequence for the style. It begins with a reset, so that no
+    /// attributes carry over from the previous style.
+    pub fn escape(&self, depth: ColourDepth) -> String {
+        let mut params = vec!["0".to_string()];
+        for (enabled, param) in [
+            (self.bold, "1"),
+            (self.dim, "2"),
+            (self.italic, "3"),
+            (self.underline, "4"),
+            (self.reverse, "7"),
+        ] {
+            if enabled {
+                params.push(param.to_string());
+            }
+        }
+        if let Some(fg) = self.fg {
+            params.push(fg.reduce(depth).sgr(false));
+        }
+        if let Some(bg) = self.bg {
+            params.push(bg.reduce(depth).sgr(true));
+        }
+        format!("\x1b[{}m", params.join(";"))
+    }
+}
+
+impl FromStr for Style {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut style = Self::default();
+        let mut words = s.split_whitespace();
+        while let Some(word) = words.next() {
+            match word.to_ascii_lowercase().as_str() {
+                "bold" => style.bold = true,
+                "dim" => style.dim = true,
+                "italic" => style.italic = true,
+                "underline" => style.underline = true,
+                "reverse" => style.reverse = true,
+                "plain" | "default" => (),
+                "on" => {
+                    let colour = words.next().ok_or("missing colour after \"on\"")?;
+                    style.bg = Some(colour.parse()?);
+                }
+                _ => style.fg = Some(word.parse()?),
+            }
+        }
+        Ok(style)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ThemeName {
+    #[default]
+    Dark,
+    Light,
+    HighContrast,
+}
+
+const fn named(n: u8) -> Colour {
+    Colour::Named(n)
+}
+
+const fn fixed(n: u8) -> Colour {
+    Colour::Fixed(n)
+}
+
+const PLAIN: Style = Style {
+    fg: None,
+    bg: None,
+    bold: false,
+    dim: false,
+    italic: false,
+    underline: false,
+    reverse: false,
+};
+
+/// The style of each component of the output
+#[derive(Clone, Debug)]
+pub struct ThemeStyles {
+    pub timestamp: Style,
+    /// The time since the previous record, by its size, when shown
+    pub delta_short: Style,
+    pub delta_medium: Style,
+    pub delta_long: Style,
+    pub error: Style,
+    pub warn: Style,
+    pub info: Style,
+    pub debug: Style,
+    pub trace: Style,
+    pub target: Style,
+    pub span_name: Style,
+    /// The separators and braces of the span context
+    pub punctuation: Style,
+    pub field_key: Style,
+    pub field_value: Style,
+    /// Matches of `--grep`
+    pub highlight: Style,
+    /// The gap between groups of `--grep` matches
+    pub separator: Style,
+    pub status_bar: Style,
+    /// The tags of merged files, used in turn
+    pub tags: Vec<Style>,
+    /// The colours targets are picked from with `--target-colors`
+    pub target_palette: Vec<Style>,
+}
+
+impl ThemeStyles {
+    pub fn builtin(name: ThemeName) -> Self {
+        let tags = |colours: [Colour; 6]| colours.map(|colour| Style::fg(colour).bold()).to_vec();
+        let palette =
+            |colours: &[Colour]| colours.iter().map(|colour| Style::fg(*colour)).collect();
+        match name {
+            ThemeName::Dark => Self {
+                timestamp: Style::fg(named(8)),
+                delta_short: Style::fg(named(8)),
+                delta_medium: Style::fg(named(3)),
+                delta_long: Style::fg(named(9)).bold(),
+                error: Style::fg(named(9)),
+                warn: Style::fg(named(11)),
+                info: Style::fg(named(10)),
+                debug: Style::fg(named(12)),
+                trace: Style::fg(named(13)),
+                target: Style::fg(named(8)),
+                span_name: PLAIN.bold(),
+                punctuation: Style::fg(named(8)),
+                field_key: Style {
+                    dim: true,
+                    italic: true,
+                    ..PLAIN
+                },
+                field_value: Style::fg(named(6)),
+                highlight: Style {
+                    reverse: true,
+                    ..PLAIN
+                },
+                separator: Style::fg(named(8)),
+                status_bar: PLAIN.on(named(8)),
+                tags: tags([6, 5, 3, 4, 2, 1].map(named)),
+                target_palette: palette(
+                    &[
+                        39, 75, 81, 114, 150, 179, 209, 204, 170, 141, 110, 73, 180, 216, 147, 121,
+                    ]
+                    .map(fixed),
+                ),
+            },
+            ThemeName::Light => Self {
+                timestamp: Style::fg(fixed(244)),
+                delta_short: Style::fg(fixed(244)),
+                delta_medium: Style::fg(fixed(130)),
+                delta_long: Style::fg(fixed(160)).bold(),
+                error: Style::fg(fixed(160)).bold(),
+                warn: Style::fg(fixed(130)).bold(),
+                info: Style::fg(fixed(28)),
+                debug: Style::fg(fixed(25)),
+                trace: Style::fg(fixed(90)),
+                target: Style::fg(fixed(242)),
+                span_name: Style::fg(fixed(236)).bold(),
+                punctuation: Style::fg(fixed(246)),
+                field_key: Style {
+                    italic: true,
+                    ..Style::fg(fixed(243))
+                },
+                field_value: Style::fg(fixed(24)),
+                highlight: PLAIN.on(fixed(222)),
+                separator: Style::fg(fixed(246)),
+                status_bar: Style::fg(fixed(235)).on(fixed(252)),
+                tags: tags([30, 127, 136, 26, 64, 124].map(fixed)),
+                target_palette: palette(
+                    &[
+                        25, 30, 64, 94, 130, 124, 127, 91, 55, 24, 28, 136, 166, 161, 96, 66,
+                    ]
+                    .map(fixed),
+                ),
+            },
+            ThemeName::HighContrast => Self {
+                timestamp: Style::fg(named(15)),
+                delta_short: Style::fg(named(15)),
+                delta_medium: Style::fg(named(11)).bold(),
+                delta_long: Style::fg(named(15)).on(named(1)).bold(),
+                error: Style::fg(named(15)).on(named(1)).bold(),
+                warn: Style::fg(named(0)).on(named(11)).bold(),
+                info: Style::fg(named(10)).bold(),
+                debug: Style::fg(named(14)).bold(),
+                trace: Style::fg(named(13)).bold(),
+                target: Style {
+                    underline: true,
+                    ..Style::fg(named(15))
+                },
+                span_name: Style::fg(named(15)).bold(),
+                punctuation: Style::fg(named(15)),
+                field_key: Style::fg(named(14)),
+                field_value: Style::fg(named(11)).bold(),
+                highlight: Style::fg(named(0)).on(named(14)).bold(),
+                separator: Style::fg(named(15)).bold(),
+                status_bar: Style::fg(named(0)).on(named(15)),
+                tags: tags([14, 13, 11, 12, 10, 9].map(named)),
+                target_palette: palette(&[14, 13, 11, 12, 10, 9].map(named)),
+            },
+        }
+    }
+
+    /// Set the style of a component by its name in [`ThemeStyles`]
+    pub fn set(&mut self, component: &str, style: Style) -> Result<(), String> {
+        let field = match component {
+            "timestamp" => &mut self.timestamp,
+            "delta_short" => &mut self.delta_short,
+            "delta_medium" => &mut self.delta_medium,
+            "delta_long" => &mut self.delta_long,
+            "error" => &mut self.error,
+            "warn" => &mut self.warn,
+            "info" => &mut self.info,
+            "debug" => &mut self.debug,
+            "trace" => &mut self.trace,
+            "target" => &mut self.target,
+            "span_name" => &mut self.span_name,
+            "punctuation" => &mut self.punctuation,
+            "field_key" => &mut self.field_key,
+            "field_value" => &mut self.field_value,
+            "highlight" => &mut self.highlight,
+            "separator" => &mut self.separator,
+            "status_bar" => &mut self.status_bar,
+            _ => return Err(format!("unknown theme component {component:?}")),
+        };
+        *field = style;
+        Ok(())
+    }
+}
+
+/// Whether targets are coloured by name, and whether by their full path or crate
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TargetColours {
+    #[default]
+    Off,
+    Target,
+    Crate,
+}
+
+/// The 64-bit FNV-1a hash, used as it is stable, unlike the hasher of the standard library
+fn fnv1a(s: &str) -> u64 {
+    s.bytes().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// The escape sequences of a theme for a particular terminal
+#[derive(Clone, Debug, Default)]
+pub struct Theme {
+    pub timestamp: String,
+    pub delta_short: String,
+    pub delta_medium: String,
+    pub delta_long: String,
+    levels: [String; 5],
+    pub target: String,
+    pub span_name: String,
+    pub punctuation: String,
+    pub field_key: String,
+    pub field_value: String,
+    pub highlight: String,
+    pub separator: String,
+    pub status_bar: String,
+    /// The level names of the status bar, drawn over its background
+    status_levels: [String; 5],
+    pub tags: Vec<String>,
+    target_palette: Vec<String>,
+    pub target_colours: TargetColours,
+    /// Returns to the terminal's default style
+    pub reset: String,
+}
+
+impl Theme {
+    pub fn new(styles: &ThemeStyles, depth: ColourDepth) -> Self {
+        let escape = |style: &Style| style.escape(depth);
+        let on_status_bar = |style: &Style| {
+            escape(&Style {
+                bg: style.bg.or(styles.status_bar.bg),
+                ..*style
+            })
+        };
+        Self {
+            timestamp: escape(&styles.timestamp),
+            delta_short: escape(&styles.delta_short),
+            delta_medium: escape(&styles.delta_medium),
+            delta_long: escape(&styles.delta_long),
+            levels: [
+                escape(&styles.error),
+                escape(&styles.warn),
+                escape(&styles.info),
+                escape(&styles.debug),
+                escape(&styles.trace),
+            ],
+            target: escape(&styles.target),
+            span_name: escape(&styles.span_name),
+            punctuation: escape(&styles.punctuation),
+            field_key: escape(&styles.field_key),
+            field_value: escape(&styles.field_value),
+            // Layered over the colours of the line rather than replacing them
+            highlight: escape(&styles.highlight).replacen("\x1b[0;", "\x1b[", 1),
+            separator: escape(&styles.separator),
+            status_bar: escape(&styles.status_bar),
+            status_levels: [
+                on_status_bar(&styles.error),
+                on_status_bar(&styles.warn),
+                on_status_bar(&styles.info),
+                on_status_bar(&styles.debug),
+                on_status_bar(&styles.trace),
+            ],
+            tags: styles.tags.iter().map(escape).collect(),
+            target_palette: styles.target_palette.iter().map(escape).collect(),
+            target_colours: TargetColours::Off,
+            reset: "\x1b[0m".to_string(),
+        }
+    }
+
+    /// A theme without any colour
+    pub fn plain() -> Self {
+        Self::default()
+    }
+
+    pub fn level(&self, log_type: LogType) -> &str {
+        &self.levels[log_type as usize]
+    }
+
+    pub fn status_level(&self, log_type: LogType) -> &str {
+        &self.status_levels[log_type as usize]
+    }
+
+    /// The style of a target. With `--target-colors` this is picked from the palette
+    /// by a hash of the name, so that it is the same across runs and machines.
+    pub fn target(&self, target: &str) -> &str {
+        let name = match self.target_colours {
+            TargetColours::Off => return &self.target,
+            TargetColours::Target => target,
+            TargetColours::Crate => target.split("::").next().unwrap_or(target),
+        };
+        match self.target_palette.len() {
+            0 => &self.target,
+            len => &self.target_palette[(fnv1a(name) % len as u64) as usize],
+        }
+    }
+
+    pub fn tag(&self, i: usize) -> &str {
+        match self.tags.len() {
+            0 => "",
+            len => &self.tags[i % len],
+        }
+    }
+}
@@ -0,0 +1,392 @@
+//! Interpretation and display of the TIMESTAMP component of a line.
+//!
+use chrono::format::{Item, StrftimeItems};
+use chrono::{
+    DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime, NaiveTime, SecondsFormat, TimeDelta,
+    Utc,
+};
+use chrono_tz::Tz;
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::theme::Theme;
+
+/// Parse an RFC 3339 timestamp, as written by tracing's default timer
+pub fn parse_timestamp(timestamp: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(timestamp.trim())
+        .ok()
+        .map(|timestamp| timestamp.with_timezone(&Utc))
+}
+
+/// A time given on the command line
+#[derive(Clone, Copy, Debug)]
+pub enum TimeArg {
+    At(DateTime<Utc>),
+    /// A time on the day of the logs
+    TimeOfDay(NaiveTime),
+    /// A duration before now
+    Ago(TimeDelta),
+}
+
+impl TimeArg {
+    /// The time, if it does not depend on the date of the logs
+    pub fn absolute(&self) -> Option<DateTime<Utc>> {
+        match self {
+            Self::At(time) => Some(*time),
+            Self::TimeOfDay(_) => None,
+            Self::Ago(duration) => Some(Utc::now() - *duration),
+        }
+    }
+
+    /// The time, taking a time of day to be on `date`
+    pub fn on(&self, date: NaiveDate) -> DateTime<Utc> {
+        match self {
+            Self::TimeOfDay(time) => date.and_time(*time).and_utc(),
+            _ => self.absolute().unwrap_or_default(),
+        }
+    }
+}
+
+/// Parse a time given on the command line, eg. `2025-08-28T04:50:00Z`,
+/// `2025-08-28 04:50`, `2025-08-28`, a time of day such as `05:10`, or a duration
+/// before now such as `15m`. Times without an offset are taken as UTC.
+pub fn parse_time_arg(s: &str) -> Result<TimeArg, String> {
+    let s = s.trim();
+    if let Some(duration) = parse_duration(s) {
+        return Ok(TimeArg::Ago(duration));
+    }
+    if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        return Ok(TimeArg::At(time.with_timezone(&Utc)));
+    }
+    let normalised = s.replacen(' ', "T", 1);
+    if let Ok(time) = DateTime::parse_from_str(&normalised, "%Y-%m-%dT%H:%M%:z") {
+        return Ok(TimeArg::At(time.with_timezone(&Utc)));
+    }
+    let naive = normalised.trim_end_matches('Z');
+    for format in ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%dT%H:%M"] {
+        if let Ok(time) = NaiveDateTime::parse_from_str(naive, format) {
+            return Ok(TimeArg::At(time.and_utc()));
+        }
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(naive, "%Y-%m-%d") {
+        return Ok(TimeArg::At(date.and_time(NaiveTime::MIN).and_utc()));
+    }
+    for format in ["%H:%M:%S%.f", "%H:%M"] {
+        if let Ok(time) = NaiveTime::parse_from_str(naive, format) {
+            return Ok(TimeArg::TimeOfDay(time));
+        }
+    }
+    Err(format!("unrecognised time {s:?}"))
+}
+
+/// Parse a positive interval given on the command line, in the same form as the
+/// durations of [`parse_time_arg`]
+pub fn parse_interval(s: &str) -> Result<TimeDelta, String> {
+    parse_duration(s.trim())
+        .filter(|interval| *interval > TimeDelta::zero())
+        .ok_or_else(|| format!("invalid interval {s:?}, expected eg. 30s, 5m or 1h"))
+}
+
+/// Parse a duration such as `500ms`, `30s`, `15m`, `2h`, `1d` or `1w`
+fn parse_duration(s: &str) -> Option<TimeDelta> {
+    let unit_start = s.find(|c: char| !c.is_ascii_digit())?;
+    let count: i64 = s[..unit_start].parse().ok()?;
+    match &s[unit_start..] {
+        "ms" => TimeDelta::try_milliseconds(count),
+        "s" => TimeDelta::try_seconds(count),
+        "m" => TimeDelta::try_minutes(count),
+        "h" => TimeDelta::try_hours(count),
+        "d" => TimeDelta::try_days(count),
+        "w" => TimeDelta::try_weeks(count),
+        _ => None,
+    }
+}
+
+/// How the TIMESTAMP of each record is shown
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TimestampMode {
+    /// As written in the log
+    #[default]
+    Absolute,
+    /// The time since the first record shown, eg. +12.345s
+    Relative,
+    /// The time since the previous record shown, eg. Δ 3ms
+    Delta,
+    /// Both the relative and delta times
+    Both,
+}
+
+/// The time zone absolute timestamps are shown in
+#[derive(Clone, Copy, Debug)]
+pub enum Zone {
+    Local,
+    Named(Tz),
+    Fixed(FixedOffset),
+}
+
+/// Parse a time zone given on the command line: `local`, an IANA name such as
+/// `Australia/Brisbane`, or an offset such as `+10:00`
+pub fn parse_zone(s: &str) -> Result<Zone, String> {
+    let s = s.trim();
+    if s.eq_ignore_ascii_case("local") {
+        return Ok(Zone::Local);
+    }
+    if s.eq_ignore_ascii_case("utc") || s == "Z" {
+        return Ok(Zone::Named(Tz::UTC));
+    }
+    if let Ok(tz) = s.parse() {
+        return Ok(Zone::Named(tz));
+    }
+    // Offsets are ASCII, which the slicing below relies on
+    if s.starts_with(['+', '-']) && s.is_ascii() {
+        let offset = match s.len() {
+            3 => format!("{s}:00"),
+            5 => format!("{}:{}", &s[..3], &s[3..]),
+            _ => s.to_string(),
+        };
+        if let Ok(offset) = offset.parse() {
+            return Ok(Zone::Fixed(offset));
+        }
+    }
+    Err(format!("unrecognised time zone {s:?}"))
+}
+
+/// A strftime format for absolute timestamps
+#[derive(Clone, Debug)]
+pub struct TimeFormat(Vec<Item<'static>>);
+
+/// The format of rewritten timestamps if only the time zone is given
+const DEFAULT_TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.6f%:z";
+
+/// Parse a strftime format such as `%H:%M:%S%.3f`
+pub fn parse_time_format(s: &str) -> Result<TimeFormat, String> {
+    StrftimeItems::new(s)
+        .parse_to_owned()
+        .map(TimeFormat)
+        .map_err(|_| format!("invalid time format {s:?}"))
+}
+
+/// Rewrites the TIMESTAMP of each record shown, remembering the times of the first and
+/// previous records
+pub struct TimestampDisplay {
+    mode: TimestampMode,
+    /// Absolute timestamps are only rewritten if one of the zone and format is given
+    zone: Option<Zone>,
+    format: Option<TimeFormat>,
+    first: Option<DateTime<Utc>>,
+    previous: Option<DateTime<Utc>>,
+}
+
+impl TimestampDisplay {
+    pub fn new(mode: TimestampMode, zone: Option<Zone>, format: Option<TimeFormat>) -> Self {
+        let format = match (&zone, format) {
+            (Some(_), None) => parse_time_format(DEFAULT_TIME_FORMAT).ok(),
+            (_, format) => format,
+        };
+        Self {
+            mode,
+            zone,
+            format,
+            first: None,
+            previous: None,
+        }
+    }
+
+    /// Format the time in the chosen zone, UTC by default
+    fn format_absolute(&self, time: DateTime<Utc>, format: &TimeFormat) -> String {
+        let items = format.0.iter();
+        match self.zone {
+            Some(Zone::Local) => time.with_timezone(&Local).format_with_items(items),
+            Some(Zone::Named(tz)) => time.with_timezone(&tz).format_with_items(items),
+            Some(Zone::Fixed(offset)) => time.with_timezone(&offset).format_with_items(items),
+            None => time.format_with_items(items),
+        }
+        .to_string()
+    }
+
+    /// Colour the TIMESTAMP component, which includes its trailing whitespace. It is
+    /// written unchanged if it cannot be parsed.
+    pub fn render(&mut self, timestamp: &str, theme: &Theme) -> String {
+        let trimmed = timestamp.trim_end();
+        let padding = &timestamp[trimmed.len()..];
+        let time = match (self.mode, &self.format) {
+            (TimestampMode::Absolute, None) => None,
+            _ => parse_timestamp(trimmed),
+        };
+        let Some(time) = time else {
+            return format!("{}{timestamp}", theme.timestamp);
+        };
+        if let (TimestampMode::Absolute, Some(format)) = (self.mode, &self.format) {
+            let absolute = self.format_absolute(time, format);
+            return format!("{}{absolute}{padding}", theme.timestamp);
+        }
+        let first = *self.first.get_or_insert(time);
+        let previous = self.previous.replace(time).unwrap_or(time);
+
+        let relative = format!("{}{:>11}", theme.timestamp, format_relative(time - first));
+        let delta = time - previous;
+        let delta_style = match delta.abs() {
+            d if d < TimeDelta::milliseconds(100) => &theme.delta_short,
+            d if d < TimeDelta::seconds(1) => &theme.delta_medium,
+            _ => &theme.delta_long,
+        };
+        let delta = format!("{delta_style}Δ {:>8}", format_delta(delta));
+        match self.mode {
+            TimestampMode::Relative => format!("{relative}{padding}"),
+            TimestampMode::Delta => format!("{delta}{padding}"),
+            _ => format!("{relative} {delta}{padding}"),
+        }
+    }
+}
+
+/// Format a time in UTC with only as many fractional digits as it needs, for reports
+pub fn format_time(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+/// Format a duration from the first record to the millisecond, eg. `+1h02m03.456s`
+fn format_relative(duration: TimeDelta) -> String {
+    let sign = if duration < TimeDelta::zero() {
+        '-'
+    } else {
+        '+'
+    };
+    let ms = duration.abs().num_milliseconds();
+    let (h, m, s, ms) = (ms / 3_600_000, ms / 60_000 % 60, ms / 1000 % 60, ms % 1000);
+    match (h, m) {
+        (0, 0) => format!("{sign}{s}.{ms:03}s"),
+        (0, _) => format!("{sign}{m}m{s:02}.{ms:03}s"),
+        _ => format!("{sign}{h}h{m:02}m{s:02}.{ms:03}s"),
+    }
+}
+
+/// Format a duration between records in its largest unit, eg. `850µs`, `3ms`, `1.204s`
+pub fn format_delta(duration: TimeDelta) -> String {
+    let sign = if duration < TimeDelta::zero() {
+        "-"
+    } else {
+        ""
+    };
+    let duration = duration.abs();
+    let us = duration.num_microseconds().unwrap_or(i64::MAX);
+    let s = duration.num_seconds();
+    if us < 1000 {
+        format!("{sign}{us}µs")
+    } else if us < 1_000_000 {
+        format!("{sign}{}ms", us / 1000)
+    } else if s < 60 {
+        format!("{sign}{s}.{:03}s", us / 1000 % 1000)
+    } else if s < 3600 {
+        format!("{sign}{}m{:02}s", s / 60, s % 60)
+    } else {
+        format!("{sign}{}h{:02}m", s / 3600, s / 60 % 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        match parse_time_arg(s) {
+            Ok(TimeArg::At(time)) => time,
+            other => panic!("expected a time for {s:?}, got {other:?}"),
+        }
+    }
+
+    fn time_of_day(s: &str) -> NaiveTime {
+        match parse_time_arg(s) {
+            Ok(TimeArg::TimeOfDay(time)) => time,
+            other => panic!("expected a time of day for {s:?}, got {other:?}"),
+        }
+    }
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        parse_timestamp(s).expect("the timestamp is valid")
+    }
+
+    #[test]
+    fn times() {
+        assert_eq!(at("2025-08-28T04:50:00Z"), utc("2025-08-28T04:50:00Z"));
+        assert_eq!(at("2025-08-28T14:50:00+10:00"), utc("2025-08-28T04:50:00Z"));
+        assert_eq!(at("2025-08-28T14:50+10:00"), utc("2025-08-28T04:50:00Z"));
+        assert_eq!(at("2025-08-28 04:50"), utc("2025-08-28T04:50:00Z"));
+        assert_eq!(at("2025-08-28 04:50:12.5"), utc("2025-08-28T04:50:12.5Z"));
+        assert_eq!(at("2025-08-28T04:50:12Z"), utc("2025-08-28T04:50:12Z"));
+        assert_eq!(at(" 2025-08-28 "), utc("2025-08-28T00:00:00Z"));
+    }
+
+    #[test]
+    fn times_of_day() {
+        let hms = |h, m, s| NaiveTime::from_hms_opt(h, m, s).unwrap();
+        assert_eq!(time_of_day("05:10"), hms(5, 10, 0));
+        assert_eq!(time_of_day("05:10:30"), hms(5, 10, 30));
+        assert_eq!(
+            time_of_day("05:10:30.250"),
+            NaiveTime::from_hms_milli_opt(5, 10, 30, 250).unwrap()
+        );
+    }
+
+    #[test]
+    fn durations_ago() {
+        assert!(
+            matches!(parse_time_arg("15m"), Ok(TimeArg::Ago(d)) if d == TimeDelta::minutes(15))
+        );
+        let ago = parse_time_arg("2h").unwrap().absolute().unwrap();
+        let expected = Utc::now() - TimeDelta::hours(2);
+        assert!((ago - expected).abs() < TimeDelta::seconds(5));
+    }
+
+    #[test]
+    fn invalid_times() {
+        for s in ["", "yesterday", "25:00", "2025-13-01", "15x", "m"] {
+            assert!(parse_time_arg(s).is_err(), "{s:?} should not parse");
+        }
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("500ms"), Some(TimeDelta::milliseconds(500)));
+        assert_eq!(parse_duration("30s"), Some(TimeDelta::seconds(30)));
+        assert_eq!(parse_duration("15m"), Some(TimeDelta::minutes(15)));
+        assert_eq!(parse_duration("2h"), Some(TimeDelta::hours(2)));
+        assert_eq!(parse_duration("1d"), Some(TimeDelta::days(1)));
+        assert_eq!(parse_duration("1w"), Some(TimeDelta::weeks(1)));
+        assert_eq!(parse_duration("0s"), Some(TimeDelta::zero()));
+        for s in ["", "10", "s", "1.5s", "-1s", "10y", "1 s"] {
+            assert_eq!(parse_duration(s), None, "{s:?} should not parse");
+        }
+    }
+
+    #[test]
+    fn intervals() {
+        assert_eq!(parse_interval(" 1m "), Ok(TimeDelta::minutes(1)));
+        assert!(parse_interval("0s").is_err());
+        assert!(parse_interval("soon").is_err());
+    }
+
+    fn offset(zone: Result<Zone, String>) -> i32 {
+        match zone {
+            Ok(Zone::Fixed(offset)) => offset.local_minus_utc(),
+            _ => panic!("expected a fixed offset"),
+        }
+    }
+
+    #[test]
+    fn zones() {
+        assert!(matches!(parse_zone("local"), Ok(Zone::Local)));
+        assert!(matches!(parse_zone("UTC"), Ok(Zone::Named(Tz::UTC))));
+        assert!(matches!(
+            parse_zone("Australia/Brisbane"),
+            Ok(Zone::Named(Tz::Australia__Brisbane))
+        ));
+        assert_eq!(offset(parse_zone("+10")), 36000);
+        assert_eq!(offset(parse_zone("-0330")), -12600);
+        assert_eq!(offset(parse_zone("+05:45")), 20700);
+        assert!(parse_zone("Mars/Olympus").is_err());
+        // Not a char boundary at the byte offsets an offset is split at
+        assert!(parse_zone("+1é1").is_err());
+        assert!(parse_zone("+é").is_err());
+    }
+}
@@ -0,0 +1,453 @@
+//! A full-screen viewer which understands the structure of the log.
+//!
+//! The viewer state and drawing are independent of the terminal: [`Viewer::render`]
+//! produces the lines of the screen for a given size and [`Viewer::handle_key`] applies
+//! a key press, so only [`run`] needs a real terminal.
+//!
+//! Keys:
+//!   q, Esc           quit
+//!   j, k, arrows     scroll a line
+//!   Space, b, PgDn/PgUp  scroll a page
+//!   g, G, Home/End   jump to the start or end
+//!   n, N             jump to the next or previous ERROR or WARN
+//!   1 to 5           toggle ERROR, WARN, INFO, DEBUG and TRACE records
+//!   /                narrow to targets containing the typed text
+//!   c                collapse continuation lines
+//!
+use std::io::{self, Write};
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::{cursor, execute, queue, style, terminal};
+
+use crate::ansi;
+use crate::parse::LogType;
+use crate::theme::Theme;
+
+/// A record prepared for the viewer
+pub struct Entry {
+    /// The coloured lines of the record, the first being the log line itself
+    pub lines: Vec<String>,
+    /// None if the record failed to parse
+    pub log_type: Option<LogType>,
+    pub target: String,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Action {
+    Continue,
+    Quit,
+}
+
+pub struct Viewer {
+    entries: Vec<Entry>,
+    counts: [usize; 5],
+    hidden: [bool; 5],
+    target_filter: String,
+    /// The target filter being typed, if the prompt is open
+    prompt: Option<String>,
+    collapse: bool,
+    /// The visible rows as (entry, line) indices, where a line equal to the number of
+    /// lines of the entry is the summary of its collapsed continuation lines
+    rows: Vec<(usize, usize)>,
+    /// The position of the record of each row among the visible records, from 1
+    row_positions: Vec<usize>,
+    /// The number of visible records
+    visible: usize,
+    top: usize,
+    page_height: usize,
+    theme: Theme,
+}
+
+impl Viewer {
+    pub fn new(entries: Vec<Entry>, theme: Theme) -> Self {
+        let mut counts = [0; 5];
+        for log_type in entries.iter().filter_map(|entry| entry.log_type) {
+            counts[log_type as usize] += 1;
+        }
+        let mut viewer = Self {
+            entries,
+            counts,
+            hidden: [false; 5],
+            target_filter: String::new(),
+            prompt: None,
+            collapse: false,
+            rows: Vec::new(),
+            row_positions: Vec::new(),
+            visible: 0,
+            top: 0,
+            page_height: 1,
+            theme,
+        };
+        viewer.update_rows();
+        viewer
+    }
+
+    fn is_visible(&self, entry: &Entry) -> bool {
+        let filter = self.prompt.as_ref().unwrap_or(&self.target_filter);
+        match entry.log_type {
+            Some(log_type) => {
+                !self.hidden[log_type as usize] && entry.target.contains(filter.as_str())
+            }
+            None => filter.is_empty(),
+        }
+    }
+
+    /// Rebuild the visible rows, keeping the record at the top of the screen in view
+    fn update_rows(&mut self) {
+        let top_entry = self.rows.get(self.top).map(|(entry, _)| *entry);
+        self.rows.clear();
+        self.row_positions.clear();
+        self.visible = 0;
+        for (i, entry) in self.entries.iter().enumerate() {
+            if !self.is_visible(entry) {
+                continue;
+            }
+            self.visible += 1;
+            if self.collapse && entry.lines.len() > 1 {
+                self.rows.push((i, 0));
+                self.rows.push((i, entry.lines.len()));
+            } else {
+                self.rows
+                    .extend((0..entry.lines.len()).map(|line| (i, line)));
+            }
+            self.row_positions.resize(self.rows.len(), self.visible);
+        }
+        self.top = top_entry
+            .and_then(|top_entry| self.rows.iter().position(|(entry, _)| *entry >= top_entry))
+            .unwrap_or(0);
+        self.clamp_top();
+    }
+
+    fn max_top(&self) -> usize {
+        self.rows.len().saturating_sub(self.page_height)
+    }
+
+    fn clamp_top(&mut self) {
+        self.top = self.top.min(self.max_top());
+    }
+
+    fn scroll(&mut self, amount: isize) {
+        self.top = self.top.saturating_add_signed(amount);
+        self.clamp_top();
+    }
+
+    fn is_problem(&self, row: &(usize, usize)) -> bool {
+        row.1 == 0
+            && matches!(
+                self.entries[row.0].log_type,
+                Some(LogType::Error | LogType::Warn)
+            )
+    }
+
+    /// Move the next ERROR or WARN after the top of the screen to the top
+    fn next_problem(&mut self) {
+        if let Some(offset) = self.rows[(self.top + 1).min(self.rows.len())..]
+            .iter()
+            .position(|row| self.is_problem(row))
+        {
+            self.top += offset + 1;
+        }
+    }
+
+    fn previous_problem(&mut self) {
+        if let Some(row) = self.rows[..self.top]
+            .iter()
+            .rposition(|row| self.is_problem(row))
+        {
+            self.top = row;
+        }
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) -> Action {
+        if let Some(prompt) = &mut self.prompt {
+            match key.code {
+                KeyCode::Enter => {
+                    self.target_filter = self.prompt.take().unwrap_or_default();
+                }
+                KeyCode::Esc => self.prompt = None,
+                KeyCode::Backspace => {
+                    prompt.pop();
+                }
+                KeyCode::Char(c) => prompt.push(c),
+                _ => return Action::Continue,
+            }
+            self.update_rows();
+            return Action::Continue;
+        }
+
+        let page = self.page_height as isize;
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Action::Quit,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                return Action::Quit;
+            }
+            KeyCode::Char('j') | KeyCode::Down | KeyCode::Enter => self.scroll(1),
+            KeyCode::Char('k') | KeyCode::Up => self.scroll(-1),
+            KeyCode::Char(' ') | KeyCode::PageDown => self.scroll(page),
+            KeyCode::Char('b') | KeyCode::PageUp => self.scroll(-page),
+            KeyCode::Char('g') | KeyCode::Home => self.top = 0,
+            KeyCode::Char('G') | KeyCode::End => self.top = self.max_top(),
+            KeyCode::Char('n') => self.next_problem(),
+            KeyCode::Char('N') => self.previous_problem(),
+            KeyCode::Char(c @ '1'..='5') => {
+                let level = c as usize - '1' as usize;
+                self.hidden[level] = !self.hidden[level];
+                self.update_rows();
+            }
+            KeyCode::Char('/') => {
+                self.prompt = Some(self.target_filter.clone());
+            }
+            KeyCode::Char('c') => {
+                self.collapse = !self.collapse;
+                self.update_rows();
+            }
+            _ => (),
+        }
+        Action::Continue
+    }
+
+    /// Draw the screen as `height` lines, the last of which is the status bar
+    pub fn render(&mut self, width: usize, height: usize) -> Vec<String> {
+        self.page_height = height.saturating_sub(1).max(1);
+        self.clamp_top();
+
+        let mut screen = Vec::with_capacity(height);
+        for &(entry, line) in self.rows.iter().skip(self.top).take(self.page_height) {
+            let entry = &self.entries[entry];
+            let text = match entry.lines.get(line) {
+                Some(text) => text.clone(),
+                None => {
+                    let hidden = entry.lines.len() - 1;
+                    let plural = if hidden == 1 { "" } else { "s" };
+                    format!(
+                        "{}  … {hidden} more line{plural}{}",
+                        self.theme.punctuation, self.theme.reset
+                    )
+                }
+            };
+            screen.push(ansi::truncate(&text, width));
+        }
+        screen.resize(self.page_height, String::new());
+        screen.push(self.status_bar(width));
+        screen
+    }
+
+    fn status_bar(&self, width: usize) -> String {
+        if let Some(prompt) = &self.prompt {
+            return ansi::truncate(&format!("target: {prompt}\x1b[7m \x1b[0m"), width);
+        }
+        let mut bar = String::new();
+        for (i, log_type) in LogType::ALL.iter().enumerate() {
+            let hidden = if self.hidden[i] { "\x1b[2;9m" } else { "" };
+            bar.push_str(&format!(
+                "{}{hidden}{} {}{}  ",
+                self.theme.status_level(*log_type),
+                log_type.as_str(),
+                self.counts[i],
+                self.theme.status_bar,
+            ));
+        }
+        if !self.target_filter.is_empty() {
+            bar.push_str(&format!("target: {}  ", self.target_filter));
+        }
+        if self.collapse {
+            bar.push_str("collapsed  ");
+        }
+        let shown = self.row_positions.get(self.top).copied().unwrap_or(0);
+        bar.push_str(&format!("{shown}/{}", self.visible));
+        let bar = ansi::truncate(&bar, width);
+        let padding = width.saturating_sub(ansi::strip(&bar).chars().count());
+        format!(
+            "{}{bar}{}{}",
+            self.theme.status_bar,
+            " ".repeat(padding),
+            self.theme.reset
+        )
+    }
+}
+
+/// Run the viewer until it is quit
+pub fn run(entries: Vec<Entry>, theme: Theme) -> io::Result<()> {
+    let mut viewer = Viewer::new(entries, theme);
+    let mut stdout = io::stdout();
+    terminal::enable_raw_mode()?;
+    execute!(stdout, terminal::EnterAlternateScreen, cursor::Hide)?;
+    let result = event_loop(&mut viewer, &mut stdout);
+    execute!(stdout, cursor::Show, terminal::LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()?;
+    result
+}
+
+fn event_loop(viewer: &mut Viewer, stdout: &mut io::Stdout) -> io::Result<()> {
+    loop {
+        let (width, height) = terminal::size()?;
+        for (y, line) in viewer
+            .render(width as usize, height as usize)
+            .iter()
+            .enumerate()
+        {
+            queue!(
+                stdout,
+                cursor::MoveTo(0, y as u16),
+                terminal::Clear(terminal::ClearType::CurrentLine),
+                style::Print(line)
+            )?;
+        }
+        stdout.flush()?;
+
+        if let Event::Key(key) = event::read()?
+            && key.kind != KeyEventKind::Release
+            && viewer.handle_key(key) == Action::Quit
+        {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(log_type: LogType, target: &str, lines: &[&str]) -> Entry {
+        Entry {
+            lines: lines.iter().map(|line| line.to_string()).collect(),
+            log_type: Some(log_type),
+            target: target.to_string(),
+        }
+    }
+
+    fn viewer() -> Viewer {
+        Viewer::new(
+            vec![
+                entry(LogType::Info, "app", &["one"]),
+                entry(LogType::Error, "app::db", &["two"]),
+                entry(LogType::Info, "app::http", &["three"]),
+                entry(LogType::Warn, "app::db", &["four"]),
+                entry(LogType::Info, "app", &["five", "  continued", "  again"]),
+            ],
+            Theme::plain(),
+        )
+    }
+
+    fn press(viewer: &mut Viewer, keys: &str) {
+        for c in keys.chars() {
+            viewer.handle_key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+        }
+    }
+
+    fn press_code(viewer: &mut Viewer, code: KeyCode) -> Action {
+        viewer.handle_key(KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    /// The screen without colours, with the status bar's padding removed
+    fn screen(viewer: &mut Viewer, height: usize) -> Vec<String> {
+        viewer
+            .render(80, height)
+            .iter()
+            .map(|line| ansi::strip(line).trim_end().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn status_bar_counts() {
+        let mut viewer = viewer();
+        assert_eq!(
+            screen(&mut viewer, 10)[9],
+            "ERROR 1  WARN 1  INFO 3  DEBUG 0  TRACE 0  1/5"
+        );
+        screen(&mut viewer, 3);
+        press(&mut viewer, "j");
+        assert_eq!(
+            screen(&mut viewer, 3)[2],
+            "ERROR 1  WARN 1  INFO 3  DEBUG 0  TRACE 0  2/5"
+        );
+        // Continuation lines belong to their record
+        press(&mut viewer, "G");
+        assert!(screen(&mut viewer, 3)[2].ends_with(" 5/5"));
+        // Only the visible records are counted
+        press(&mut viewer, "3g");
+        assert!(screen(&mut viewer, 2)[1].ends_with(" 1/2"));
+        press(&mut viewer, "j");
+        assert!(screen(&mut viewer, 2)[1].ends_with(" 2/2"));
+        press(&mut viewer, "3");
+        assert!(screen(&mut viewer, 2)[1].ends_with(" 4/5"));
+    }
+
+    #[test]
+    fn jump_to_problems() {
+        let mut viewer = viewer();
+        assert_eq!(screen(&mut viewer, 3)[0], "one");
+        press(&mut viewer, "n");
+        assert_eq!(screen(&mut viewer, 3)[0], "two");
+        press(&mut viewer, "n");
+        assert_eq!(screen(&mut viewer, 3)[0], "four");
+        // There are no more, so the screen stays put
+        press(&mut viewer, "n");
+        assert_eq!(screen(&mut viewer, 3)[0], "four");
+        press(&mut viewer, "N");
+        assert_eq!(screen(&mut viewer, 3)[0], "two");
+        press(&mut viewer, "N");
+        assert_eq!(screen(&mut viewer, 3)[0], "two");
+    }
+
+    #[test]
+    fn toggle_levels() {
+        let mut viewer = viewer();
+        press(&mut viewer, "3");
+        assert_eq!(
+            screen(&mut viewer, 4),
+            [
+                "two",
+                "four",
+                "",
+                "ERROR 1  WARN 1  INFO 3  DEBUG 0  TRACE 0  1/2"
+            ]
+        );
+        // The hidden level is struck through
+        assert!(viewer.render(80, 4)[3].contains("\x1b[2;9mINFO 3"));
+        press(&mut viewer, "12");
+        assert_eq!(screen(&mut viewer, 2)[0], "");
+        press(&mut viewer, "123");
+        assert_eq!(screen(&mut viewer, 10).len(), 10);
+        assert_eq!(screen(&mut viewer, 10)[0..3], ["one", "two", "three"]);
+    }
+
+    #[test]
+    fn target_prompt() {
+        let mut viewer = viewer();
+        press(&mut viewer, "/db");
+        // The records are narrowed while typing
+        assert_eq!(screen(&mut viewer, 4), ["two", "four", "", "target: db"]);
+        press_code(&mut viewer, KeyCode::Enter);
+        assert_eq!(
+            screen(&mut viewer, 4)[3],
+            "ERROR 1  WARN 1  INFO 3  DEBUG 0  TRACE 0  target: db  1/2"
+        );
+
+        // Escape abandons the edit, keeping the previous filter
+        press(&mut viewer, "/");
+        press_code(&mut viewer, KeyCode::Backspace);
+        press_code(&mut viewer, KeyCode::Backspace);
+        assert_eq!(screen(&mut viewer, 10)[0..3], ["one", "two", "three"]);
+        assert_eq!(press_code(&mut viewer, KeyCode::Esc), Action::Continue);
+        assert_eq!(screen(&mut viewer, 4)[0..2], ["two", "four"]);
+
+        // Outside of the prompt, keys act as usual
+        assert_eq!(press_code(&mut viewer, KeyCode::Esc), Action::Quit);
+    }
+
+    #[test]
+    fn collapse_continuation_lines() {
+        let mut viewer = viewer();
+        screen(&mut viewer, 5);
+        press(&mut viewer, "G");
+        assert_eq!(
+            screen(&mut viewer, 5)[0..4],
+            ["four", "five", "  continued", "  again"]
+        );
+        press(&mut viewer, "c");
+        let screen = screen(&mut viewer, 5);
+        assert_eq!(screen[2..4], ["five", "  … 2 more lines"]);
+        assert!(screen[4].contains("collapsed"));
+    }
+}